@@ -0,0 +1,113 @@
+use crate::file_format::{
+    get_environment_errors, get_qualified_dependency_path, get_resource_by_path_mut,
+    get_resource_path, Config, Resource, ResourceWithDependencies,
+};
+use indexmap::IndexMap;
+use std::io;
+
+/// Applies the overrides of an environment to the resources, leaving out disabled resources and
+/// the dependencies on them. The environments are removed from the configuration, which then only
+/// describes what to deploy.
+pub(crate) fn apply_environment(config: &mut Config, environment_name: &str) -> io::Result<()> {
+    let validation_errors = get_environment_errors(config, environment_name);
+    if let Some(validation_error) = validation_errors.into_iter().next() {
+        return Err(validation_error.into());
+    }
+
+    let environment = config
+        .environments
+        .take()
+        .and_then(|mut environments| environments.shift_remove(environment_name))
+        .unwrap();
+
+    let mut disabled_paths = Vec::new();
+    for (resource_path, resource_override) in environment.resources {
+        if resource_override.enabled == Some(false) {
+            remove_resource_by_path(&mut config.resources, resource_path.as_str());
+            disabled_paths.push(resource_path);
+            continue;
+        }
+
+        // Overrides of a resource within a disabled group have nothing left to apply to
+        let Some(ResourceWithDependencies { resource, .. }) =
+            get_resource_by_path_mut(&mut config.resources, resource_path.as_str())
+        else {
+            continue;
+        };
+        match resource {
+            Resource::HelmRemote { helm_remote } => {
+                if let Some(namespace) = resource_override.namespace {
+                    helm_remote.namespace = namespace;
+                }
+                if let Some(chart_version) = resource_override.chart_version {
+                    helm_remote.chart_version = chart_version;
+                }
+                if let Some(values) = resource_override.values {
+                    helm_remote.values = Some(values);
+                }
+            }
+            Resource::HelmLocal { helm_local } => {
+                if let Some(namespace) = resource_override.namespace {
+                    helm_local.namespace = namespace;
+                }
+                if let Some(values) = resource_override.values {
+                    helm_local.values = Some(values);
+                }
+            }
+            _ => {}
+        }
+    }
+    remove_dependencies(&mut config.resources, None, disabled_paths.as_slice());
+
+    Ok(())
+}
+
+fn remove_resource_by_path(
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    resource_path: &str,
+) {
+    match resource_path.rsplit_once(":") {
+        None => {
+            resources.shift_remove(resource_path);
+        }
+        Some((group_path, resource_key)) => {
+            if let Some(ResourceWithDependencies {
+                resource: Resource::Group { group },
+                ..
+            }) = get_resource_by_path_mut(resources, group_path)
+            {
+                group.shift_remove(resource_key);
+            }
+        }
+    }
+}
+
+/// Removes the dependencies on disabled resources, and on resources within disabled groups, so
+/// that resources depending on them run without them
+fn remove_dependencies(
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    disabled_paths: &[String],
+) {
+    for (resource_key, resource) in resources.iter_mut() {
+        if let Some(ref mut depends_on) = resource.depends_on {
+            depends_on.retain(|dependency| {
+                let dependency_path = match get_qualified_dependency_path(dependency) {
+                    Some(dependency_path) => dependency_path.to_string(),
+                    None => get_resource_path(resources_namespace, dependency),
+                };
+                !disabled_paths.iter().any(|disabled_path| {
+                    dependency_path == *disabled_path
+                        || dependency_path.starts_with(format!("{}:", disabled_path).as_str())
+                })
+            });
+        }
+        if let Resource::Group { ref mut group } = resource.resource {
+            remove_dependencies(
+                group,
+                Some(get_resource_path(resources_namespace, resource_key).as_str()),
+                disabled_paths,
+            );
+        }
+    }
+}
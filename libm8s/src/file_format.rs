@@ -1,16 +1,46 @@
-use indexmap::{indexmap, IndexMap};
+#[cfg(test)]
+use indexmap::indexmap;
+use indexmap::IndexMap;
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use std::io::Error;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 #[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Variables available as `${NAME}` in resources, overridden by `--var-file` and `--set`
+    pub vars: Option<IndexMap<String, String>>,
     pub helm: Option<Helm>,
     pub resources: IndexMap<String, ResourceWithDependencies>,
+    /// Overrides applied to resources with `--env NAME`
+    pub environments: Option<IndexMap<String, Environment>>,
+    /// Retry policy of the resources that don't set their own
+    pub retry: Option<Retry>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Environment {
+    /// Overrides by resource path, like `group:resource` for resources within groups
+    pub resources: IndexMap<String, ResourceOverride>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct ResourceOverride {
+    /// Set to `false` to leave the resource out of the environment
+    pub enabled: Option<bool>,
+    /// Replaces the namespace of `helmRemote` and `helmLocal` resources
+    pub namespace: Option<String>,
+    /// Replaces the chart version of `helmRemote` resources
+    pub chart_version: Option<String>,
+    /// Replaces the values files of `helmRemote` and `helmLocal` resources
+    pub values: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
@@ -18,6 +48,41 @@ pub struct Config {
 #[serde(deny_unknown_fields)]
 pub struct Helm {
     pub repositories: Option<Vec<HelmRepository>>,
+    /// OCI registries to log into, for `oci://` charts that need credentials
+    pub registries: Option<Vec<HelmRegistry>>,
+    /// Options of all `helmRemote` and `helmLocal` resources, unless they set their own
+    pub defaults: Option<HelmOptions>,
+}
+
+/// Options passed to `helm install` and `helm upgrade`
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct HelmOptions {
+    /// Creates the namespace of the release if it doesn't exist
+    pub create_namespace: Option<bool>,
+    /// Waits until the resources of the release are ready
+    pub wait: Option<bool>,
+    /// Waits until the jobs of the release are complete, along with `wait`
+    pub wait_for_jobs: Option<bool>,
+    /// Time to wait for each Kubernetes operation, like `5m0s`
+    pub timeout: Option<String>,
+    /// Rolls back the release when installing or upgrading fails
+    pub atomic: Option<bool>,
+    /// Description of the release revision
+    pub description: Option<String>,
+}
+
+impl HelmOptions {
+    /// Sets the options the resource doesn't set itself
+    pub(crate) fn apply_defaults(&mut self, defaults: &HelmOptions) {
+        self.create_namespace = self.create_namespace.or(defaults.create_namespace);
+        self.wait = self.wait.or(defaults.wait);
+        self.wait_for_jobs = self.wait_for_jobs.or(defaults.wait_for_jobs);
+        self.timeout = self.timeout.take().or(defaults.timeout.clone());
+        self.atomic = self.atomic.or(defaults.atomic);
+        self.description = self.description.take().or(defaults.description.clone());
+    }
 }
 
 #[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
@@ -28,15 +93,49 @@ pub struct HelmRepository {
     pub url: String,
 }
 
+/// Credentials are read from environment variables, so they don't end up in the configuration
 #[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct HelmRegistry {
+    /// Host of the registry, like `registry.example.com` for `oci://registry.example.com/org/chart`
+    pub host: String,
+    /// Name of the environment variable holding the username
+    pub username_env: String,
+    /// Name of the environment variable holding the password
+    pub password_env: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct ResourceWithDependencies {
     #[serde(flatten)]
     pub resource: Resource,
+    /// Keys of resources in the same group, or paths like `group:resource` of resources anywhere
+    /// else, starting from the top level
     pub depends_on: Option<Vec<String>>,
+    /// Runs the resource again when it fails, set on a group for all its resources
+    pub retry: Option<Retry>,
+    /// How long each attempt of the resource may take, like `10m`, before its commands are
+    /// terminated, set on a group for all its resources
+    pub timeout: Option<String>,
+    /// Shell scripts running before and after the resource
+    pub hooks: Option<Hooks>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+impl ResourceWithDependencies {
+    /// Dependencies on resources in the same group, referenced by their key
+    pub(crate) fn get_sibling_dependencies(&self) -> Vec<String> {
+        self.depends_on
+            .iter()
+            .flatten()
+            .filter(|depends_on| !depends_on.contains(":"))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(untagged)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
@@ -46,10 +145,16 @@ pub enum Resource {
     #[serde(rename_all = "camelCase")]
     Manifest { manifest: Manifest },
     #[serde(rename_all = "camelCase")]
+    Kustomize { kustomize: Kustomize },
+    #[serde(rename_all = "camelCase")]
     HelmRemote { helm_remote: HelmRemote },
     #[serde(rename_all = "camelCase")]
     HelmLocal { helm_local: HelmLocal },
     #[serde(rename_all = "camelCase")]
+    Include { include: Include },
+    #[serde(rename_all = "camelCase")]
+    Wait { wait: Wait },
+    #[serde(rename_all = "camelCase")]
     Group {
         group: IndexMap<String, ResourceWithDependencies>,
     },
@@ -60,21 +165,175 @@ pub enum Resource {
     },
 }
 
-#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+impl Resource {
+    /// Name of the resource type, as written in the configuration file
+    pub fn get_type_name(&self) -> &'static str {
+        match self {
+            Resource::Shell { .. } => "shell",
+            Resource::Manifest { .. } => "manifest",
+            Resource::Kustomize { .. } => "kustomize",
+            Resource::HelmRemote { .. } => "helmRemote",
+            Resource::HelmLocal { .. } => "helmLocal",
+            Resource::Include { .. } => "include",
+            Resource::Group { .. } => "group",
+            Resource::Wait { .. } => "wait",
+            Resource::Noop { .. } => "noop",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Shell {
     pub input: String,
+    pub teardown: Option<String>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct Manifest {
+    /// File, directory or glob pattern like `crds/*.yaml`
+    pub path: Option<String>,
+    /// Files, directories or glob patterns, applied after `path`
+    pub paths: Option<Vec<String>>,
+    /// Also applies the files in subdirectories of directories
+    pub recursive: Option<bool>,
+    /// Decrypts all the files with `sops`, not only those ending in `.enc.yaml`
+    pub sops: Option<bool>,
+}
+
+impl Manifest {
+    /// `path` followed by `paths`, as written in the configuration
+    pub fn get_paths(&self) -> Vec<String> {
+        self.path
+            .iter()
+            .chain(self.paths.iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// Files to apply, in the order of the paths they match. Directories and glob patterns match
+    /// their files sorted by path, and files matched twice are only applied once.
+    pub(crate) fn get_files(&self) -> io::Result<Vec<String>> {
+        let mut files = Vec::new();
+        for path in self.get_paths() {
+            let matched_files =
+                get_manifest_path_files(path.as_str(), self.recursive == Some(true))?;
+            if matched_files.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Manifest path doesn't match any file: {}", path),
+                ));
+            }
+            for matched_file in matched_files {
+                if !files.contains(&matched_file) {
+                    files.push(matched_file);
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+#[test]
+fn test_manifest_get_files_expands_paths_in_order() {
+    let manifest = Manifest {
+        path: Some("tests/manifests".to_string()),
+        paths: Some(vec![
+            "tests/manifest.yaml".to_string(),
+            "tests/manifests/*.yaml".to_string(),
+        ]),
+        recursive: Some(true),
+        sops: None,
+    };
+    assert_eq!(
+        vec![
+            "tests/manifests/a.yml",
+            "tests/manifests/b.yaml",
+            "tests/manifests/crds/crd.yaml",
+            "tests/manifest.yaml",
+        ],
+        manifest.get_files().unwrap()
+    );
+}
+
+#[test]
+fn test_manifest_get_files_fails_when_glob_matches_no_file() {
+    let manifest = Manifest {
+        path: Some("tests/manifests/*.json".to_string()),
+        paths: None,
+        recursive: None,
+        sops: None,
+    };
+    assert_eq!(
+        "Manifest path doesn't match any file: tests/manifests/*.json",
+        manifest.get_files().err().unwrap().to_string()
+    );
+}
+
+/// Like `kubectl apply -f`, directories only match their YAML and JSON files. Glob patterns and
+/// directories can match no file at all.
+fn get_manifest_path_files(path: &str, recursive: bool) -> io::Result<Vec<String>> {
+    let is_glob_pattern = path.contains(['*', '?', '[']);
+    let mut files = Vec::new();
+    if is_glob_pattern {
+        let paths = glob::glob(path).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid glob pattern {}: {}", path, err),
+            )
+        })?;
+        for path in paths {
+            let path = path.map_err(io::Error::from)?;
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+    } else if Path::new(path).is_dir() {
+        collect_manifest_directory_files(Path::new(path), recursive, &mut files)?;
+    } else {
+        // Files that don't exist are left for `check_files_exist` and kubectl to report
+        files.push(PathBuf::from(path));
+    }
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|file| file.to_string_lossy().to_string())
+        .collect())
+}
+
+fn collect_manifest_directory_files(
+    directory: &Path,
+    recursive: bool,
+    files: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                collect_manifest_directory_files(path.as_path(), recursive, files)?;
+            }
+        } else if path.extension().is_some_and(|extension| {
+            ["yaml", "yml", "json"].contains(&&*extension.to_string_lossy())
+        }) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Directory with a `kustomization.yaml` file, applied with `kubectl apply -k`
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Kustomize {
     pub path: String,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+/// Release of a chart from a Helm repository or an OCI registry
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct HelmRemote {
@@ -82,17 +341,183 @@ pub struct HelmRemote {
     pub namespace: String,
     pub chart_name: String,
     pub chart_version: String,
+    /// Values files, applied in order, later files taking precedence
     pub values: Option<Vec<String>>,
+    /// Decrypts all the values files with `sops`, not only those ending in `.enc.yaml`
+    pub sops: Option<bool>,
+    /// Values written inline, applied after the `values` files and taking precedence over them
+    pub values_inline: Option<IndexMap<String, serde_json::Value>>,
+    /// Values set with `--set`, taking precedence over `values` and `valuesInline`
+    #[serde(default, deserialize_with = "deserialize_helm_set")]
+    #[schemars(schema_with = "helm_set_schema")]
+    pub set: Option<IndexMap<String, String>>,
+    /// Values set with `--set-string`, kept as strings and taking precedence over all other values
+    pub set_string: Option<IndexMap<String, String>>,
+    #[serde(flatten)]
+    pub options: HelmOptions,
+    /// What to do when the release is stuck in a failed or pending state, `error` by default
+    pub failed_release_policy: Option<FailedReleasePolicy>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Clone, JsonSchema)]
+/// Handling of releases whose last install, upgrade or rollback failed or never finished
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum FailedReleasePolicy {
+    /// Fails, leaving the release to be fixed by hand
+    #[default]
+    Error,
+    /// Rolls back to the last deployed revision, then upgrades
+    Rollback,
+    /// Uninstalls the release, then installs it again
+    Reinstall,
+}
+
+/// Shell scripts running around a resource, run with `bash -c` like `shell` resources. They get
+/// `M8S_RESOURCE_PATH`, `M8S_RESOURCE_TYPE` and, for Helm resources, `M8S_RELEASE_NAME` and
+/// `M8S_RELEASE_NAMESPACE` in their environment.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs before the resource, which fails without running when the script fails
+    pub pre: Option<String>,
+    /// Runs once the resource succeeded, the resource fails when the script fails
+    pub post: Option<String>,
+    /// Runs when the resource or its other hooks failed, with the error in `M8S_RESOURCE_ERROR`
+    pub on_failure: Option<String>,
+}
+
+/// Runs a failed resource again after a delay, doubling the delay with each attempt
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Retry {
+    /// Maximum number of times the resource runs, including the first one
+    pub attempts: u32,
+    /// Delay before the second attempt, like `5s`, 1 second by default
+    pub initial_delay: Option<String>,
+    /// Longest delay between two attempts, like `1m`, 30 seconds by default
+    pub max_delay: Option<String>,
+}
+
+/// Waits until objects of the cluster are ready, polling `kubectl` until the condition is met or
+/// the timeout expires. Exactly one of `rollout`, `condition` and `crd` is set.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Wait {
+    /// Workload like `deployment/web`, waited for with `kubectl rollout status`
+    pub rollout: Option<String>,
+    /// Condition of objects, waited for with `kubectl wait --for=condition=...`
+    pub condition: Option<WaitCondition>,
+    /// Name of a CustomResourceDefinition like `certificates.cert-manager.io`, waited for until it
+    /// is established
+    pub crd: Option<String>,
+    /// Namespace of the workload or objects
+    pub namespace: Option<String>,
+    /// How long to wait before failing, like `5m` or `1m30s`, 5 minutes by default
+    pub timeout: Option<String>,
+    /// How long to wait between checks, like `10s`, 5 seconds by default
+    pub poll_interval: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct WaitCondition {
+    /// Objects like `pod/web-0` or `pods`, as passed to `kubectl wait`
+    pub object: String,
+    /// Only waits for the objects with these labels, like `app=web`
+    pub selector: Option<String>,
+    /// Condition like `Ready` or `Available`
+    pub condition: String,
+}
+
+/// Another deployment file, whose resources become a group. Its paths are relative to its own
+/// directory and its Helm repositories are added to those of the including file.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct Include {
+    pub path: String,
+}
+
+/// Release of a chart from a local directory
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
 pub struct HelmLocal {
     pub name: String,
     pub namespace: String,
     pub chart_path: String,
+    /// Values files, applied in order, later files taking precedence
     pub values: Option<Vec<String>>,
+    /// Decrypts all the values files with `sops`, not only those ending in `.enc.yaml`
+    pub sops: Option<bool>,
+    /// Values written inline, applied after the `values` files and taking precedence over them
+    pub values_inline: Option<IndexMap<String, serde_json::Value>>,
+    /// Values set with `--set`, taking precedence over `values` and `valuesInline`
+    #[serde(default, deserialize_with = "deserialize_helm_set")]
+    #[schemars(schema_with = "helm_set_schema")]
+    pub set: Option<IndexMap<String, String>>,
+    /// Values set with `--set-string`, kept as strings and taking precedence over all other values
+    pub set_string: Option<IndexMap<String, String>>,
+    #[serde(flatten)]
+    pub options: HelmOptions,
+    /// What to do when the release is stuck in a failed or pending state, `error` by default
+    pub failed_release_policy: Option<FailedReleasePolicy>,
+}
+
+/// Reads the values of `set`, which can be written as strings, numbers or booleans since Helm
+/// parses them anyway
+fn deserialize_helm_set<'de, D>(
+    deserializer: D,
+) -> Result<Option<IndexMap<String, String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let Some(values) = Option::<IndexMap<String, serde_yaml::Value>>::deserialize(deserializer)?
+    else {
+        return Ok(None);
+    };
+    values
+        .into_iter()
+        .map(|(key, value)| match value {
+            serde_yaml::Value::String(value) => Ok((key, value)),
+            serde_yaml::Value::Number(value) => Ok((key, value.to_string())),
+            serde_yaml::Value::Bool(value) => Ok((key, value.to_string())),
+            _ => Err(serde::de::Error::custom(format!(
+                "value of {} in set must be a string, a number or a boolean",
+                key
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+fn helm_set_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+    use schemars::schema::{InstanceType, ObjectValidation, SchemaObject};
+
+    let value_schema = SchemaObject {
+        instance_type: Some(
+            vec![
+                InstanceType::String,
+                InstanceType::Number,
+                InstanceType::Boolean,
+            ]
+            .into(),
+        ),
+        ..Default::default()
+    };
+    SchemaObject {
+        instance_type: Some(vec![InstanceType::Object, InstanceType::Null].into()),
+        object: Some(Box::new(ObjectValidation {
+            additional_properties: Some(Box::new(value_schema.into())),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
 }
 
 pub fn create_json_schema() -> io::Result<String> {
@@ -174,89 +599,552 @@ fn test_analyse_cycles_returns_ok_when_no_cycle_is_detected() {
     );
 }
 
+/// Problem found while validating the configuration, `resource_path` is the path of the resource
+/// it relates to, in the `group:child` format used for RESOURCES on the command line.
+#[derive(Debug, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationError {
+    pub resource_path: Option<String>,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(resource_path: &str, message: String) -> ValidationError {
+        ValidationError {
+            resource_path: Some(resource_path.to_string()),
+            message,
+        }
+    }
+}
+
+impl From<ValidationError> for io::Error {
+    fn from(validation_error: ValidationError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, validation_error.message)
+    }
+}
+
+/// Runs every check on the configuration and returns all the problems found, in contrast with the
+/// `check_*` functions which stop at the first problem.
+pub fn validate_config(config: &Config) -> Vec<ValidationError> {
+    get_config_errors(config, true)
+}
+
+/// Checks the configuration like [`validate_config`], but `check_files` false skips checking that
+/// the files of the resources exist, for commands that don't read them.
+fn get_config_errors(config: &Config, check_files: bool) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
+    for environment_name in config.environments.iter().flat_map(|e| e.keys()) {
+        validation_errors.extend(get_environment_errors(config, environment_name));
+    }
+    validation_errors.extend(get_resource_keys_format_errors(&config.resources, None));
+    validation_errors.extend(get_invalid_dependencies_errors(&config.resources, None));
+    validation_errors.extend(get_dependency_cycles_errors(&config.resources, None));
+    if check_files {
+        validation_errors.extend(get_files_exist_errors(&config.resources, None));
+    }
+    validation_errors.extend(get_wait_errors(&config.resources, None));
+    if let Some(ref retry) = config.retry {
+        validation_errors.extend(get_retry_errors(retry).into_iter().map(|message| {
+            ValidationError {
+                resource_path: None,
+                message: format!("Configuration is invalid, {}", message),
+            }
+        }));
+    }
+    validation_errors.extend(get_run_policy_errors(&config.resources, None));
+    validation_errors.extend(get_helm_remote_repositories_errors(
+        &config.resources,
+        &config
+            .helm
+            .as_ref()
+            .and_then(|helm| helm.repositories.clone()),
+        None,
+    ));
+    validation_errors
+}
+
+/// Same as [`validate_config`], but returns a single error listing all the problems found.
+pub fn check_config(config: &Config) -> io::Result<()> {
+    into_config_result(validate_config(config))
+}
+
+/// Same as [`check_config`], without checking that the files of the resources exist, for commands
+/// like `down` that don't need values files or charts to tear resources down.
+pub fn check_config_without_files(config: &Config) -> io::Result<()> {
+    into_config_result(get_config_errors(config, false))
+}
+
+fn into_config_result(validation_errors: Vec<ValidationError>) -> io::Result<()> {
+    match validation_errors.len() {
+        0 => Ok(()),
+        1 => Err(validation_errors[0].clone().into()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Configuration is invalid, found {} problems:\n{}",
+                validation_errors.len(),
+                validation_errors
+                    .iter()
+                    .map(|validation_error| format!("  - {}", validation_error.message))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ),
+        )),
+    }
+}
+
+fn check_first_error(validation_errors: Vec<ValidationError>) -> io::Result<()> {
+    match validation_errors.into_iter().next() {
+        None => Ok(()),
+        Some(validation_error) => Err(validation_error.into()),
+    }
+}
+
+pub(crate) fn get_resource_path(resources_namespace: Option<&str>, resource_key: &str) -> String {
+    match resources_namespace {
+        None => resource_key.to_string(),
+        Some(ns) => format!("{}:{}", ns, resource_key),
+    }
+}
+
+/// Returns the paths of the resources a resource depends on, with keys of resources in the same
+/// group turned into paths.
+fn get_dependency_paths(
+    resource: &ResourceWithDependencies,
+    resources_namespace: Option<&str>,
+) -> Vec<String> {
+    resource
+        .depends_on
+        .iter()
+        .flatten()
+        .map(
+            |depends_on| match get_qualified_dependency_path(depends_on) {
+                Some(dependency_path) => dependency_path.to_string(),
+                None => get_resource_path(resources_namespace, depends_on),
+            },
+        )
+        .collect()
+}
+
+/// Returns the path from the top level of a dependency on a resource of another group, written
+/// `group:child`, or `:name` for a resource at the top level. Dependencies on resources of the
+/// same group, written with their key, return `None`.
+pub(crate) fn get_qualified_dependency_path(depends_on: &str) -> Option<&str> {
+    match depends_on.strip_prefix(":") {
+        Some(dependency_path) => Some(dependency_path),
+        None if depends_on.contains(":") => Some(depends_on),
+        None => None,
+    }
+}
+
+#[test]
+fn test_get_qualified_dependency_path_anchors_leading_colon_at_top_level() {
+    assert_eq!(Some("db"), get_qualified_dependency_path(":db"));
+    assert_eq!(Some("infra:db"), get_qualified_dependency_path("infra:db"));
+    assert_eq!(None, get_qualified_dependency_path("db"));
+}
+
+/// Finds a resource from its path in the `group:child` format used for RESOURCES on the command
+/// line, starting from the top level.
+pub(crate) fn get_resource_by_path<'a>(
+    resources: &'a IndexMap<String, ResourceWithDependencies>,
+    resource_path: &str,
+) -> Option<&'a ResourceWithDependencies> {
+    let mut resource_path_parts = resource_path.split(":");
+    let mut resource = resources.get(resource_path_parts.next()?)?;
+    for resource_key in resource_path_parts {
+        match &resource.resource {
+            Resource::Group { group } => resource = group.get(resource_key)?,
+            _ => return None,
+        }
+    }
+    Some(resource)
+}
+
+pub(crate) fn get_resource_by_path_mut<'a>(
+    resources: &'a mut IndexMap<String, ResourceWithDependencies>,
+    resource_path: &str,
+) -> Option<&'a mut ResourceWithDependencies> {
+    let mut resource_path_parts = resource_path.split(":");
+    let mut resource = resources.get_mut(resource_path_parts.next()?)?;
+    for resource_key in resource_path_parts {
+        match &mut resource.resource {
+            Resource::Group { group } => resource = group.get_mut(resource_key)?,
+            _ => return None,
+        }
+    }
+    Some(resource)
+}
+
+#[test]
+fn test_get_resource_by_path_looks_into_groups() {
+    let resources = indexmap! {
+        "a".to_string() => ResourceWithDependencies {
+            resource: Resource::Group {
+                group: indexmap! {
+                    "b".to_string() => ResourceWithDependencies {
+                        resource: Resource::Noop { noop: "".to_string() },
+                        depends_on: None,
+                        retry: None,
+                        hooks: None,
+                        timeout: None,
+                    },
+                },
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        },
+    };
+    assert!(get_resource_by_path(&resources, "a").is_some());
+    assert!(get_resource_by_path(&resources, "a:b").is_some());
+    assert!(get_resource_by_path(&resources, "a:c").is_none());
+    assert!(get_resource_by_path(&resources, "a:b:c").is_none());
+}
+
+/// Returns the path of every resource that is not a group, in order, along with the paths of the
+/// resources that are not groups it depends on. Depending on a group means depending on all the
+/// resources within it, and resources within a group depend on what the group depends on.
+pub(crate) fn get_flattened_dependencies(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> IndexMap<String, Vec<String>> {
+    let mut dependency_paths_by_resource_path = IndexMap::new();
+    collect_dependency_paths(
+        &mut dependency_paths_by_resource_path,
+        resources,
+        resources_namespace,
+        &[],
+    );
+
+    let resource_paths: Vec<String> = dependency_paths_by_resource_path.keys().cloned().collect();
+    dependency_paths_by_resource_path
+        .into_iter()
+        .map(|(resource_path, dependency_paths)| {
+            let dependencies = resource_paths
+                .iter()
+                .filter(|other_resource_path| {
+                    dependency_paths.iter().any(|dependency_path| {
+                        *other_resource_path == dependency_path
+                            || other_resource_path
+                                .starts_with(format!("{}:", dependency_path).as_str())
+                    })
+                })
+                .cloned()
+                .collect();
+            (resource_path, dependencies)
+        })
+        .collect()
+}
+
+fn collect_dependency_paths(
+    dependency_paths_by_resource_path: &mut IndexMap<String, Vec<String>>,
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    group_dependency_paths: &[String],
+) {
+    for (resource_key, resource) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        let mut dependency_paths = group_dependency_paths.to_vec();
+        dependency_paths.extend(get_dependency_paths(resource, resources_namespace));
+        match &resource.resource {
+            Resource::Group { group } => collect_dependency_paths(
+                dependency_paths_by_resource_path,
+                group,
+                Some(resource_path.as_str()),
+                &dependency_paths,
+            ),
+            _ => {
+                dependency_paths_by_resource_path.insert(resource_path, dependency_paths);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_get_flattened_dependencies_goes_through_groups() {
+    let noop = |depends_on: Option<Vec<String>>| ResourceWithDependencies {
+        resource: Resource::Noop {
+            noop: "".to_string(),
+        },
+        depends_on,
+        retry: None,
+        hooks: None,
+        timeout: None,
+    };
+    let resources = indexmap! {
+        "a".to_string() => ResourceWithDependencies {
+            resource: Resource::Group {
+                group: indexmap! {
+                    "b".to_string() => noop(None),
+                    "c".to_string() => noop(Some(vec!["d:e".to_string()])),
+                },
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        },
+        "d".to_string() => ResourceWithDependencies {
+            resource: Resource::Group {
+                group: indexmap! {
+                    "e".to_string() => noop(None),
+                },
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        },
+        "f".to_string() => noop(Some(vec!["a".to_string()])),
+    };
+    assert_eq!(
+        indexmap! {
+            "a:b".to_string() => vec![],
+            "a:c".to_string() => vec!["d:e".to_string()],
+            "d:e".to_string() => vec![],
+            "f".to_string() => vec!["a:b".to_string(), "a:c".to_string()],
+        },
+        get_flattened_dependencies(&resources, None)
+    );
+}
+
+#[test]
+fn test_get_resource_path_prefixes_namespace() {
+    assert_eq!("a", get_resource_path(None, "a"));
+    assert_eq!("a:b:c", get_resource_path(Some("a:b"), "c"));
+}
+
+/// Returns the problems with the overrides of an environment, which must exist, refer to
+/// existing resources and only override fields these resources have.
+pub(crate) fn get_environment_errors(
+    config: &Config,
+    environment_name: &str,
+) -> Vec<ValidationError> {
+    let Some(environment) = config
+        .environments
+        .as_ref()
+        .and_then(|environments| environments.get(environment_name))
+    else {
+        return vec![ValidationError {
+            resource_path: None,
+            message: format!(
+                "Configuration is invalid, environment doesn't exist: {}",
+                environment_name
+            ),
+        }];
+    };
+
+    let mut validation_errors = Vec::new();
+    for (resource_path, resource_override) in environment.resources.iter() {
+        let Some(resource) = get_resource_by_path(&config.resources, resource_path) else {
+            validation_errors.push(ValidationError::new(
+                resource_path,
+                format!(
+                    "Configuration is invalid, environment \"{}\" overrides resource that doesn't exist: {}",
+                    environment_name, resource_path
+                ),
+            ));
+            continue;
+        };
+
+        let is_helm = matches!(
+            resource.resource,
+            Resource::HelmRemote { .. } | Resource::HelmLocal { .. }
+        );
+        let is_helm_remote = matches!(resource.resource, Resource::HelmRemote { .. });
+        for (field, is_overridden, is_supported) in [
+            ("namespace", resource_override.namespace.is_some(), is_helm),
+            (
+                "chartVersion",
+                resource_override.chart_version.is_some(),
+                is_helm_remote,
+            ),
+            ("values", resource_override.values.is_some(), is_helm),
+        ] {
+            if is_overridden && !is_supported {
+                validation_errors.push(ValidationError::new(
+                    resource_path,
+                    format!(
+                        "Configuration is invalid, environment \"{}\" overrides {} of resource \"{}\", which {} resources don't have",
+                        environment_name,
+                        field,
+                        resource_path,
+                        resource.resource.get_type_name()
+                    ),
+                ));
+            }
+        }
+    }
+    validation_errors
+}
+
 pub fn check_dependency_cycles(
     resources: &IndexMap<String, ResourceWithDependencies>,
 ) -> io::Result<()> {
+    check_first_error(get_dependency_cycles_errors(resources, None))
+}
+
+fn get_dependency_cycles_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let validation_errors = get_sibling_dependency_cycles_errors(resources, resources_namespace);
+    if !validation_errors.is_empty() {
+        return validation_errors;
+    }
+
+    // Cycles between siblings show up again here, so only look once they are fixed, in order to
+    // find cycles going through dependencies on resources of other groups
+    get_cycles(&get_flattened_dependencies(resources, resources_namespace))
+        .into_iter()
+        .map(|(resource_path, cycle)| create_dependency_cycle_error(resource_path.as_str(), &cycle))
+        .collect()
+}
+
+fn get_sibling_dependency_cycles_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
     let mut dependencies_by_resource_key = IndexMap::new();
     for (resource_key, resource) in resources.iter() {
-        dependencies_by_resource_key.insert(
-            resource_key.clone(),
-            resource.depends_on.clone().unwrap_or(Vec::new()),
-        );
+        dependencies_by_resource_key
+            .insert(resource_key.clone(), resource.get_sibling_dependencies());
     }
 
+    let mut validation_errors = get_cycles(&dependencies_by_resource_key)
+        .into_iter()
+        .map(|(resource_key, cycle)| {
+            create_dependency_cycle_error(
+                get_resource_path(resources_namespace, resource_key.as_str()).as_str(),
+                &cycle,
+            )
+        })
+        .collect::<Vec<ValidationError>>();
+
+    for (resource_key, ResourceWithDependencies { resource, .. }) in resources {
+        if let Resource::Group { group } = resource {
+            let group_path = get_resource_path(resources_namespace, resource_key);
+            validation_errors.extend(get_sibling_dependency_cycles_errors(
+                group,
+                Some(group_path.as_str()),
+            ));
+        }
+    }
+
+    validation_errors
+}
+
+/// Returns the key each cycle was found from, along with the cycle itself
+fn get_cycles(
+    dependencies_by_resource_key: &IndexMap<String, Vec<String>>,
+) -> Vec<(String, Vec<String>)> {
+    let mut cycles = Vec::new();
+    let mut resource_keys_in_cycles = HashSet::new();
     for resource_key in dependencies_by_resource_key.keys() {
+        // A cycle is reported once, not once for each resource that is part of it
+        if resource_keys_in_cycles.contains(resource_key) {
+            continue;
+        }
         if let Err(cycle) = analyse_cycles(
             resource_key,
-            &dependencies_by_resource_key,
+            dependencies_by_resource_key,
             &mut HashSet::new(),
             &mut Vec::new(),
         ) {
-            return Err(create_dependency_cycle_error(resource_key, cycle));
+            resource_keys_in_cycles.extend(cycle.clone());
+            cycles.push((resource_key.clone(), cycle));
         }
     }
-    Ok(())
+    cycles
 }
 
-fn create_dependency_cycle_error(resource_key: &String, cycle: Vec<String>) -> Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+fn create_dependency_cycle_error(resource_path: &str, cycle: &[String]) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Configuration is invalid, dependency cycle for \"{}\": {}",
-            resource_key,
+            resource_path,
             cycle.join(" -> ")
         ),
     )
 }
 
-fn create_file_not_exists_error(resource_key: &str, path: &str) -> io::Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+fn create_file_not_exists_error(resource_path: &str, path: &str) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Invalid resource {}, references file that doesn't exist: {}",
-            resource_key, path
+            resource_path, path
         ),
     )
 }
 
-fn create_directory_not_exists_error(resource_key: &str, path: &str) -> io::Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+fn create_directory_not_exists_error(resource_path: &str, path: &str) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Invalid resource {}, references directory that doesn't exist: {}",
-            resource_key, path
+            resource_path, path
+        ),
+    )
+}
+
+fn create_kustomization_not_exists_error(resource_path: &str, path: &str) -> ValidationError {
+    ValidationError::new(
+        resource_path,
+        format!(
+            "Invalid resource {}, references directory without kustomization.yaml: {}",
+            resource_path, path
         ),
     )
 }
 
+/// Kustomize accepts any of these file names for the kustomization of a directory
+fn has_kustomization_file(path: &str) -> bool {
+    ["kustomization.yaml", "kustomization.yml", "Kustomization"]
+        .iter()
+        .any(|file_name| fs::exists(Path::new(path).join(file_name)).unwrap_or(false))
+}
+
 pub fn check_helm_remote_repositories(
     resources: &IndexMap<String, ResourceWithDependencies>,
     helm_repositories: &Option<Vec<HelmRepository>>,
 ) -> io::Result<()> {
+    check_first_error(get_helm_remote_repositories_errors(
+        resources,
+        helm_repositories,
+        None,
+    ))
+}
+
+fn get_helm_remote_repositories_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    helm_repositories: &Option<Vec<HelmRepository>>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
     for (resource_key, ResourceWithDependencies { resource, .. }) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
         match resource {
+            // OCI charts are pulled from their registry directly, without a repository
+            Resource::HelmRemote { helm_remote }
+                if helm_remote.chart_name.starts_with("oci://") => {}
             Resource::HelmRemote { helm_remote } => match helm_remote.chart_name.split_once("/") {
-                None => {
-                    return Err(create_invalid_helm_chart_name_error(
-                        resource_key,
-                        helm_remote,
-                    ))
-                }
+                None => validation_errors.push(create_invalid_helm_chart_name_error(
+                    resource_path.as_str(),
+                    helm_remote,
+                )),
                 Some((repository_name, _)) => match helm_repositories {
-                    None => {
-                        return Err(create_helm_no_repositories_error(
-                            resource_key,
-                            repository_name,
-                        ))
-                    }
+                    None => validation_errors.push(create_helm_no_repositories_error(
+                        resource_path.as_str(),
+                        repository_name,
+                    )),
                     Some(helm_repositories) => {
-                        if let None = helm_repositories
-                            .iter()
-                            .filter(|r| r.name == repository_name)
-                            .next()
-                        {
-                            return Err(create_helm_repository_not_exists_error(
-                                resource_key,
+                        if !helm_repositories.iter().any(|r| r.name == repository_name) {
+                            validation_errors.push(create_helm_repository_not_exists_error(
+                                resource_path.as_str(),
                                 repository_name,
                                 helm_repositories,
                             ));
@@ -264,42 +1152,55 @@ pub fn check_helm_remote_repositories(
                     }
                 },
             },
+            Resource::Group { group } => {
+                validation_errors.extend(get_helm_remote_repositories_errors(
+                    group,
+                    helm_repositories,
+                    Some(resource_path.as_str()),
+                ));
+            }
             _ => {}
         }
     }
-    Ok(())
+    validation_errors
 }
 
-fn create_invalid_helm_chart_name_error(resource_key: &String, helm_remote: &HelmRemote) -> Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+fn create_invalid_helm_chart_name_error(
+    resource_path: &str,
+    helm_remote: &HelmRemote,
+) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Invalid resource {}, chart name \"{}\" doesn't start with a repository name",
-            resource_key, helm_remote.chart_name
+            resource_path, helm_remote.chart_name
         ),
     )
 }
 
-fn create_helm_no_repositories_error(resource_key: &String, repository_name: &str) -> Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+fn create_helm_no_repositories_error(
+    resource_path: &str,
+    repository_name: &str,
+) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Invalid resource {}, repository with name \"{}\" doesn't exist, no repositories configured",
-            resource_key, repository_name
+            resource_path, repository_name
         ),
     )
 }
 
 fn create_helm_repository_not_exists_error(
-    resource_key: &String,
+    resource_path: &str,
     repository_name: &str,
-    helm_repositories: &Vec<HelmRepository>,
-) -> Error {
-    io::Error::new(
-        io::ErrorKind::InvalidData,
+    helm_repositories: &[HelmRepository],
+) -> ValidationError {
+    ValidationError::new(
+        resource_path,
         format!(
             "Invalid resource {}, repository with name \"{}\" doesn't exist, valid values are [{}]",
-            resource_key,
+            resource_path,
             repository_name,
             helm_repositories
                 .iter()
@@ -311,22 +1212,81 @@ fn create_helm_repository_not_exists_error(
 }
 
 pub fn check_files_exist(resources: &IndexMap<String, ResourceWithDependencies>) -> io::Result<()> {
+    check_first_error(get_files_exist_errors(resources, None))
+}
+
+fn get_files_exist_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
     for (resource_key, ResourceWithDependencies { resource, .. }) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
         match resource {
             Resource::Shell { .. } => {}
             Resource::Manifest { manifest, .. } => {
-                if fs::exists(manifest.path.as_str())? == false {
-                    return Err(create_file_not_exists_error(
-                        resource_key.as_str(),
-                        manifest.path.as_str(),
+                let paths = manifest.get_paths();
+                if paths.is_empty() {
+                    validation_errors.push(ValidationError::new(
+                        resource_path.as_str(),
+                        format!(
+                            "Invalid resource {}, manifest needs a path or paths",
+                            resource_path
+                        ),
+                    ));
+                }
+                for path in paths {
+                    let is_glob_or_directory =
+                        path.contains(['*', '?', '[']) || Path::new(path.as_str()).is_dir();
+                    if !is_glob_or_directory {
+                        if !fs::exists(path.as_str()).unwrap_or(false) {
+                            validation_errors.push(create_file_not_exists_error(
+                                resource_path.as_str(),
+                                path.as_str(),
+                            ));
+                        }
+                    } else if !get_manifest_path_files(
+                        path.as_str(),
+                        manifest.recursive == Some(true),
+                    )
+                    .is_ok_and(|files| !files.is_empty())
+                    {
+                        validation_errors.push(ValidationError::new(
+                            resource_path.as_str(),
+                            format!(
+                                "Invalid resource {}, references path that doesn't match any manifest file: {}",
+                                resource_path, path
+                            ),
+                        ));
+                    }
+                }
+            }
+            Resource::Kustomize { kustomize } => {
+                if !fs::exists(kustomize.path.as_str()).unwrap_or(false) {
+                    validation_errors.push(create_directory_not_exists_error(
+                        resource_path.as_str(),
+                        kustomize.path.as_str(),
+                    ));
+                } else if !has_kustomization_file(kustomize.path.as_str()) {
+                    validation_errors.push(create_kustomization_not_exists_error(
+                        resource_path.as_str(),
+                        kustomize.path.as_str(),
+                    ));
+                }
+            }
+            Resource::Include { include } => {
+                if !fs::exists(include.path.as_str()).unwrap_or(false) {
+                    validation_errors.push(create_file_not_exists_error(
+                        resource_path.as_str(),
+                        include.path.as_str(),
                     ));
                 }
             }
             Resource::HelmRemote { helm_remote } => {
                 for value in helm_remote.values.clone().unwrap_or(Vec::new()) {
-                    if fs::exists(value.as_str())? == false {
-                        return Err(create_file_not_exists_error(
-                            resource_key.as_str(),
+                    if !fs::exists(value.as_str()).unwrap_or(false) {
+                        validation_errors.push(create_file_not_exists_error(
+                            resource_path.as_str(),
                             value.as_str(),
                         ));
                     }
@@ -334,45 +1294,152 @@ pub fn check_files_exist(resources: &IndexMap<String, ResourceWithDependencies>)
             }
             Resource::HelmLocal { helm_local } => {
                 for value in helm_local.values.clone().unwrap_or(Vec::new()) {
-                    if fs::exists(value.as_str())? == false {
-                        return Err(create_file_not_exists_error(
-                            resource_key.as_str(),
+                    if !fs::exists(value.as_str()).unwrap_or(false) {
+                        validation_errors.push(create_file_not_exists_error(
+                            resource_path.as_str(),
                             value.as_str(),
                         ));
                     }
                 }
 
-                if fs::exists(helm_local.chart_path.as_str())? == false {
-                    return Err(create_directory_not_exists_error(
-                        resource_key.as_str(),
+                if !fs::exists(helm_local.chart_path.as_str()).unwrap_or(false) {
+                    validation_errors.push(create_directory_not_exists_error(
+                        resource_path.as_str(),
                         helm_local.chart_path.as_str(),
                     ));
                 }
             }
             Resource::Group { group } => {
-                check_files_exist(group)?;
+                validation_errors
+                    .extend(get_files_exist_errors(group, Some(resource_path.as_str())));
             }
-            Resource::Noop { .. } => {}
+            Resource::Wait { .. } | Resource::Noop { .. } => {}
         }
     }
-    Ok(())
+    validation_errors
+}
+
+fn get_wait_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
+    for (resource_key, ResourceWithDependencies { resource, .. }) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        match resource {
+            Resource::Wait { wait } => {
+                let conditions_count = [
+                    wait.rollout.is_some(),
+                    wait.condition.is_some(),
+                    wait.crd.is_some(),
+                ]
+                .iter()
+                .filter(|is_set| **is_set)
+                .count();
+                if conditions_count != 1 {
+                    validation_errors.push(ValidationError::new(
+                        resource_path.as_str(),
+                        format!(
+                            "Invalid resource {}, wait needs exactly one of rollout, condition and crd",
+                            resource_path
+                        ),
+                    ));
+                }
+                for duration in [&wait.timeout, &wait.poll_interval].into_iter().flatten() {
+                    if let Err(err) = crate::utils::parse_duration(duration.as_str()) {
+                        validation_errors.push(ValidationError::new(
+                            resource_path.as_str(),
+                            format!("Invalid resource {}, {}", resource_path, err),
+                        ));
+                    }
+                }
+            }
+            Resource::Group { group } => {
+                validation_errors.extend(get_wait_errors(group, Some(resource_path.as_str())));
+            }
+            _ => {}
+        }
+    }
+    validation_errors
+}
+
+/// Errors of the options changing how resources run, rather than what they do
+fn get_run_policy_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
+    for (
+        resource_key,
+        ResourceWithDependencies {
+            resource,
+            retry,
+            timeout,
+            hooks,
+            ..
+        },
+    ) in resources
+    {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        let mut messages: Vec<String> = retry.iter().flat_map(get_retry_errors).collect();
+        if hooks.is_some() && matches!(resource, Resource::Group { .. } | Resource::Include { .. })
+        {
+            messages.push(
+                "hooks can't be set on groups and includes, set them on their resources"
+                    .to_string(),
+            );
+        }
+        if let Some(Err(err)) = timeout
+            .as_ref()
+            .map(|timeout| crate::utils::parse_duration(timeout.as_str()))
+        {
+            messages.push(format!("timeout has {}", err));
+        }
+        for message in messages {
+            validation_errors.push(ValidationError::new(
+                resource_path.as_str(),
+                format!("Invalid resource {}, {}", resource_path, message),
+            ));
+        }
+        if let Resource::Group { group } = resource {
+            validation_errors.extend(get_run_policy_errors(group, Some(resource_path.as_str())));
+        }
+    }
+    validation_errors
+}
+
+fn get_retry_errors(retry: &Retry) -> Vec<String> {
+    let mut messages = Vec::new();
+    if retry.attempts == 0 {
+        messages.push("retry needs at least 1 attempt".to_string());
+    }
+    for duration in [&retry.initial_delay, &retry.max_delay]
+        .into_iter()
+        .flatten()
+    {
+        if let Err(err) = crate::utils::parse_duration(duration.as_str()) {
+            messages.push(format!("retry has {}", err));
+        }
+    }
+    messages
 }
 
 pub fn check_invalid_resource_keys(
     resources: &IndexMap<String, ResourceWithDependencies>,
 ) -> io::Result<()> {
-    let depends_on_resource_keys_invalid = get_invalid_resource_keys_for_group(resources);
+    let depends_on_resource_keys_invalid = get_invalid_dependencies(resources, resources, None)
+        .into_iter()
+        .map(|(_, depends_on_resource_key)| depends_on_resource_key);
 
     // Deduplicate invalid resource keys that appear multiple times
     let mut depends_on_resource_keys_invalid = depends_on_resource_keys_invalid
-        .into_iter()
         .collect::<HashSet<_>>()
         .into_iter()
         .collect::<Vec<String>>();
 
     depends_on_resource_keys_invalid.sort();
 
-    if depends_on_resource_keys_invalid.len() > 0 {
+    if !depends_on_resource_keys_invalid.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
             format!(
@@ -384,53 +1451,89 @@ pub fn check_invalid_resource_keys(
     Ok(())
 }
 
-fn get_invalid_resource_keys_for_group(
+fn get_invalid_dependencies_errors(
     resources: &IndexMap<String, ResourceWithDependencies>,
-) -> Vec<String> {
-    let resource_keys: Vec<String> = resources.keys().map(|k| k.to_string()).collect();
-    let depends_on_resource_keys: Vec<String> = resources
-        .iter()
-        .map(|(_, resource)| resource.depends_on.clone().unwrap_or(Vec::new()).clone())
-        .flatten()
-        .collect();
-
-    let mut depends_on_resource_keys_invalid = depends_on_resource_keys
-        .iter()
-        .filter(|item| !resource_keys.contains(item))
-        .map(|item| item.to_string())
-        .collect::<Vec<String>>();
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    get_invalid_dependencies(resources, resources, resources_namespace)
+        .into_iter()
+        .map(|(resource_path, depends_on_resource_key)| {
+            ValidationError::new(
+                resource_path.as_str(),
+                format!(
+                    "Configuration is invalid, invalid dependency for \"{}\": {}",
+                    resource_path, depends_on_resource_key
+                ),
+            )
+        })
+        .collect()
+}
 
-    for (_, resource) in resources {
-        match &resource.resource {
-            Resource::Group { group } => {
-                depends_on_resource_keys_invalid.extend(get_invalid_resource_keys_for_group(group))
+/// Returns pairs of resource path and dependency, for dependency keys that aren't siblings of the
+/// resource depending on them and dependency paths that don't lead to any resource.
+fn get_invalid_dependencies(
+    root_resources: &IndexMap<String, ResourceWithDependencies>,
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut invalid_dependencies = Vec::new();
+    for (resource_key, resource) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        for depends_on_resource_key in resource.depends_on.clone().unwrap_or(Vec::new()) {
+            let is_valid = match get_qualified_dependency_path(&depends_on_resource_key) {
+                Some(dependency_path) => {
+                    get_resource_by_path(root_resources, dependency_path).is_some()
+                }
+                None => resources.contains_key(&depends_on_resource_key),
+            };
+            if !is_valid {
+                invalid_dependencies.push((resource_path.clone(), depends_on_resource_key));
             }
-            _ => {}
+        }
+
+        if let Resource::Group { group } = &resource.resource {
+            invalid_dependencies.extend(get_invalid_dependencies(
+                root_resources,
+                group,
+                Some(resource_path.as_str()),
+            ))
         }
     }
 
-    depends_on_resource_keys_invalid
+    invalid_dependencies
 }
 
 pub fn check_resource_keys_format(
     resources: &IndexMap<String, ResourceWithDependencies>,
 ) -> io::Result<()> {
+    check_first_error(get_resource_keys_format_errors(resources, None))
+}
+
+fn get_resource_keys_format_errors(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ValidationError> {
+    let mut validation_errors = Vec::new();
     for (resource_key, resource) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
         if !is_resource_key_format_valid(resource_key.as_str()) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
+            validation_errors.push(ValidationError::new(
+                resource_path.as_str(),
                 format!(
                     "Configuration is invalid, resource key can only contain [a-zA-Z0-9]: {}",
-                    resource_key
+                    resource_path
                 ),
             ));
         }
 
         if let Resource::Group { ref group } = resource.resource {
-            check_resource_keys_format(group)?;
+            validation_errors.extend(get_resource_keys_format_errors(
+                group,
+                Some(resource_path.as_str()),
+            ));
         }
     }
-    Ok(())
+    validation_errors
 }
 
 fn is_resource_key_format_valid(key: &str) -> bool {
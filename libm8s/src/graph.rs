@@ -0,0 +1,232 @@
+use crate::file_format::{
+    get_qualified_dependency_path, get_resource_by_path, get_resource_path, Resource,
+    ResourceWithDependencies,
+};
+use indexmap::IndexMap;
+
+struct Edge {
+    from: String,
+    from_is_group: bool,
+    to: String,
+    to_is_group: bool,
+}
+
+/// Creates a Graphviz DOT graph of the resources, with groups as clusters and edges going from
+/// each dependency to the resource depending on it, that is in the order resources run.
+pub fn create_dot_graph(resources: &IndexMap<String, ResourceWithDependencies>) -> String {
+    let mut output = String::new();
+    output.push_str("digraph m8s {\n");
+    output.push_str("  compound=true;\n");
+    output.push_str("  node [shape=box];\n");
+
+    let mut edges = Vec::new();
+    write_dot_resources(&mut output, &mut edges, resources, resources, None, 1);
+
+    for edge in edges {
+        let mut attributes = Vec::new();
+        if edge.from_is_group {
+            attributes.push(format!("ltail=\"cluster_{}\"", edge.from));
+        }
+        if edge.to_is_group {
+            attributes.push(format!("lhead=\"cluster_{}\"", edge.to));
+        }
+        if attributes.is_empty() {
+            output.push_str(format!("  \"{}\" -> \"{}\";\n", edge.from, edge.to).as_str());
+        } else {
+            output.push_str(
+                format!(
+                    "  \"{}\" -> \"{}\" [{}];\n",
+                    edge.from,
+                    edge.to,
+                    attributes.join(", ")
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+fn write_dot_resources(
+    output: &mut String,
+    edges: &mut Vec<Edge>,
+    root_resources: &IndexMap<String, ResourceWithDependencies>,
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    for (resource_key, resource) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        match &resource.resource {
+            Resource::Group { group } => {
+                output.push_str(
+                    format!("{}subgraph \"cluster_{}\" {{\n", indent, resource_path).as_str(),
+                );
+                output.push_str(format!("{}  label=\"{}\";\n", indent, resource_key).as_str());
+                // Clusters can't be the end of an edge, so edges point to this node instead and
+                // are clipped to the cluster with lhead/ltail
+                output.push_str(
+                    format!(
+                        "{}  \"{}\" [shape=point, style=invis];\n",
+                        indent, resource_path
+                    )
+                    .as_str(),
+                );
+                write_dot_resources(
+                    output,
+                    edges,
+                    root_resources,
+                    group,
+                    Some(resource_path.as_str()),
+                    depth + 1,
+                );
+                output.push_str(format!("{}}}\n", indent).as_str());
+            }
+            other => {
+                output.push_str(
+                    format!(
+                        "{}\"{}\" [label=\"{}\\n{}\"];\n",
+                        indent,
+                        resource_path,
+                        resource_key,
+                        other.get_type_name()
+                    )
+                    .as_str(),
+                );
+            }
+        }
+
+        edges.extend(get_edges(
+            root_resources,
+            resources,
+            resources_namespace,
+            resource_key,
+        ));
+    }
+}
+
+/// Creates a Mermaid flowchart of the resources, with groups as subgraphs and edges going from
+/// each dependency to the resource depending on it, that is in the order resources run.
+pub fn create_mermaid_graph(resources: &IndexMap<String, ResourceWithDependencies>) -> String {
+    let mut output = String::new();
+    output.push_str("flowchart LR\n");
+
+    let mut edges = Vec::new();
+    write_mermaid_resources(&mut output, &mut edges, resources, resources, None, 1);
+
+    for edge in edges {
+        output.push_str(
+            format!(
+                "  {} --> {}\n",
+                get_mermaid_id(edge.from.as_str()),
+                get_mermaid_id(edge.to.as_str())
+            )
+            .as_str(),
+        );
+    }
+
+    output
+}
+
+fn write_mermaid_resources(
+    output: &mut String,
+    edges: &mut Vec<Edge>,
+    root_resources: &IndexMap<String, ResourceWithDependencies>,
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    for (resource_key, resource) in resources {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        match &resource.resource {
+            Resource::Group { group } => {
+                output.push_str(
+                    format!(
+                        "{}subgraph {} [\"{}\"]\n",
+                        indent,
+                        get_mermaid_id(resource_path.as_str()),
+                        resource_key
+                    )
+                    .as_str(),
+                );
+                write_mermaid_resources(
+                    output,
+                    edges,
+                    root_resources,
+                    group,
+                    Some(resource_path.as_str()),
+                    depth + 1,
+                );
+                output.push_str(format!("{}end\n", indent).as_str());
+            }
+            other => {
+                output.push_str(
+                    format!(
+                        "{}{}[\"{} ({})\"]\n",
+                        indent,
+                        get_mermaid_id(resource_path.as_str()),
+                        resource_key,
+                        other.get_type_name()
+                    )
+                    .as_str(),
+                );
+            }
+        }
+
+        edges.extend(get_edges(
+            root_resources,
+            resources,
+            resources_namespace,
+            resource_key,
+        ));
+    }
+}
+
+fn get_edges(
+    root_resources: &IndexMap<String, ResourceWithDependencies>,
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    resource_key: &str,
+) -> Vec<Edge> {
+    let resource = &resources[resource_key];
+    resource
+        .depends_on
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        // Dependencies that were not selected are not part of the graph
+        .filter_map(|depends_on| {
+            let (depends_on_resource, depends_on_resource_path) =
+                match get_qualified_dependency_path(depends_on) {
+                    Some(dependency_path) => (
+                        get_resource_by_path(root_resources, dependency_path)?,
+                        dependency_path.to_string(),
+                    ),
+                    None => (
+                        resources.get(depends_on)?,
+                        get_resource_path(resources_namespace, depends_on),
+                    ),
+                };
+            Some(Edge {
+                from: depends_on_resource_path,
+                from_is_group: matches!(depends_on_resource.resource, Resource::Group { .. }),
+                to: get_resource_path(resources_namespace, resource_key),
+                to_is_group: matches!(resource.resource, Resource::Group { .. }),
+            })
+        })
+        .collect()
+}
+
+/// Resource keys are alphanumeric, so replacing the path separator keeps identifiers unique
+fn get_mermaid_id(resource_path: &str) -> String {
+    resource_path.replace(":", "_")
+}
+
+#[test]
+fn test_get_mermaid_id_replaces_path_separator() {
+    assert_eq!("a_b_c", get_mermaid_id("a:b:c"));
+}
@@ -1,6 +1,6 @@
-use crate::file_format::HelmRepository;
+use crate::file_format::{HelmRegistry, HelmRepository};
 use log::{debug, info};
-use std::io;
+use std::{env, io};
 
 pub fn handle_helm_repositories(
     helm_repositories: &[HelmRepository],
@@ -35,3 +35,44 @@ pub fn handle_helm_repositories(
 
     Ok(())
 }
+
+/// Logs into OCI registries with `helm registry login`, with credentials read from the
+/// environment variables of each registry. The password is passed on stdin, to keep it out of
+/// process listings.
+pub fn handle_helm_registries(helm_registries: &[HelmRegistry], dry_run: bool) -> io::Result<()> {
+    info!("Logging into Helm registries...");
+
+    for registry in helm_registries {
+        let username = get_credential(registry, registry.username_env.as_str())?;
+        let password = get_credential(registry, registry.password_env.as_str())?;
+        crate::utils::run_command_with_stdin(
+            "helm",
+            &[
+                "registry",
+                "login",
+                registry.host.as_str(),
+                "--username",
+                username.as_str(),
+                "--password-stdin",
+            ],
+            password.as_str(),
+            dry_run,
+        )?;
+
+        debug!("Logged into registry {}", registry.host);
+    }
+
+    Ok(())
+}
+
+fn get_credential(registry: &HelmRegistry, env_name: &str) -> io::Result<String> {
+    env::var(env_name).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Environment variable {} for helm registry {} is not set",
+                env_name, registry.host
+            ),
+        )
+    })
+}
@@ -1,30 +1,99 @@
-use crate::file_format::{Resource, ResourceWithDependencies};
-use file_format::Config;
+use crate::file_format::{
+    get_qualified_dependency_path, get_resource_path, Resource, ResourceWithDependencies,
+};
+use file_format::{Config, Helm, HelmOptions, HelmRegistry, HelmRepository};
 use indexmap::IndexMap;
 use log::{debug, info};
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 pub mod file_format;
+pub mod graph;
 pub mod helm_repositories;
 pub mod resources;
+pub mod state;
 pub mod utils;
 
+mod environments;
+mod scheduler;
+mod sops;
+mod vars;
+
 pub trait FileReader {
     fn read_to_string(&self, file_path: &Path) -> io::Result<String>;
 }
 
+/// Options changing how the deployment file is read, as given on the command line
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Variables taking precedence over those in `vars`
+    pub vars: IndexMap<String, String>,
+    /// Environment whose overrides are applied to the resources
+    pub environment: Option<String>,
+}
+
 pub fn parse_deployment_file(
     file_reader: impl FileReader,
     deployment_file_path: &Path,
+) -> io::Result<Config> {
+    parse_deployment_file_with_options(file_reader, deployment_file_path, &ParseOptions::default())
+}
+
+pub fn parse_deployment_file_with_options(
+    file_reader: impl FileReader,
+    deployment_file_path: &Path,
+    options: &ParseOptions,
 ) -> io::Result<Config> {
     info!("Deploying from {:?}...", deployment_file_path);
 
+    let mut config = read_deployment_file(
+        &file_reader,
+        deployment_file_path,
+        &options.vars,
+        &mut Vec::new(),
+    )?;
+
+    if let Some(ref environment) = options.environment {
+        environments::apply_environment(&mut config, environment)?;
+    }
+
+    debug!("Configuration: {:?}", config);
+
+    Ok(config)
+}
+
+/// Reads a deployment file and the files it includes, with paths relative to the directory of
+/// the file they are written in. `include_chain` holds the files including this one.
+fn read_deployment_file(
+    file_reader: &impl FileReader,
+    deployment_file_path: &Path,
+    vars: &IndexMap<String, String>,
+    include_chain: &mut Vec<PathBuf>,
+) -> io::Result<Config> {
+    let normalized_deployment_file_path = normalize_path(deployment_file_path);
+    if include_chain.contains(&normalized_deployment_file_path) {
+        include_chain.push(normalized_deployment_file_path);
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Configuration is invalid, include cycle: {}",
+                include_chain
+                    .iter()
+                    .map(|path| path.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+        ));
+    }
+
     let yaml_data = match file_reader.read_to_string(deployment_file_path) {
         Err(err) => {
             return Err(io::Error::new(
                 err.kind(),
-                format!("Unable to read configuration file: {}", err),
+                format!(
+                    "Unable to read configuration file {:?}: {}",
+                    deployment_file_path, err
+                ),
             ));
         }
         Ok(s) => s,
@@ -33,24 +102,288 @@ pub fn parse_deployment_file(
         Err(err) => {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidData,
-                format!("Unable to parse configuration file: {}", err),
+                format!(
+                    "Unable to parse configuration file {:?}: {}",
+                    deployment_file_path, err
+                ),
             ));
         }
         Ok(c) => c,
     };
 
-    let deployment_file_dir = Path::new(deployment_file_path)
+    let mut config_vars = config.vars.clone().unwrap_or_default();
+    config_vars.extend(vars.clone());
+    vars::interpolate_config(&mut config, &config_vars)?;
+
+    let deployment_file_dir = deployment_file_path
         .parent()
         .unwrap_or(Path::new("."))
         .to_path_buf();
 
-    integrate_deployment_file_dir_into_paths(&mut config.resources, deployment_file_dir);
+    integrate_deployment_file_dir_into_paths(&mut config.resources, deployment_file_dir.clone());
+    for environment in config.environments.iter_mut().flat_map(|e| e.values_mut()) {
+        for resource_override in environment.resources.values_mut() {
+            for value in resource_override.values.iter_mut().flatten() {
+                let mut new_path = deployment_file_dir.clone();
+                new_path.push(value.clone());
 
-    debug!("Configuration: {:?}", config);
+                *value = new_path.to_string_lossy().to_string();
+            }
+        }
+    }
+
+    include_chain.push(normalized_deployment_file_path);
+    let mut included_helms = Vec::new();
+    include_deployment_files(
+        file_reader,
+        &mut config.resources,
+        None,
+        &mut included_helms,
+        &config_vars,
+        include_chain,
+    )?;
+    include_chain.pop();
+
+    // Defaults of included files already apply to their resources, those of this file fill in
+    // what they leave unset
+    if let Some(helm_defaults) = config.helm.as_ref().and_then(|helm| helm.defaults.clone()) {
+        apply_helm_defaults(&mut config.resources, &helm_defaults);
+    }
+
+    for included_helm in included_helms {
+        for helm_repository in included_helm.repositories.unwrap_or_default() {
+            merge_helm_repository(&mut config, helm_repository, deployment_file_path)?;
+        }
+        for helm_registry in included_helm.registries.unwrap_or_default() {
+            merge_helm_registry(&mut config, helm_registry, deployment_file_path)?;
+        }
+    }
 
     Ok(config)
 }
 
+/// Replaces `include` resources with groups of the resources of the included files, collecting
+/// their Helm configuration. Included files see the variables of the files including them, and
+/// their retry policy applies to their resources unless the `include` resource sets one.
+fn include_deployment_files(
+    file_reader: &impl FileReader,
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    included_helms: &mut Vec<Helm>,
+    vars: &IndexMap<String, String>,
+    include_chain: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    for (
+        resource_key,
+        ResourceWithDependencies {
+            resource, retry, ..
+        },
+    ) in resources
+    {
+        match resource {
+            Resource::Include { include } => {
+                let included_config = read_deployment_file(
+                    file_reader,
+                    Path::new(&include.path),
+                    vars,
+                    include_chain,
+                )?;
+                if included_config.environments.is_some() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Configuration is invalid, environments can only be defined in the top-level file, found some in {}",
+                            include.path
+                        ),
+                    ));
+                }
+
+                included_helms.extend(included_config.helm);
+                if retry.is_none() {
+                    *retry = included_config.retry;
+                }
+                let mut group = included_config.resources;
+                reroot_qualified_dependencies(
+                    &mut group,
+                    get_resource_path(resources_namespace, resource_key).as_str(),
+                );
+                *resource = Resource::Group { group };
+            }
+            Resource::Group { ref mut group } => {
+                include_deployment_files(
+                    file_reader,
+                    group,
+                    Some(get_resource_path(resources_namespace, resource_key).as_str()),
+                    included_helms,
+                    vars,
+                    include_chain,
+                )?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Qualified dependencies of an included file start from its top level, which becomes the group
+/// at `include_path`
+fn reroot_qualified_dependencies(
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    include_path: &str,
+) {
+    for resource in resources.values_mut() {
+        for depends_on in resource.depends_on.iter_mut().flatten() {
+            if let Some(rerooted) = get_qualified_dependency_path(depends_on)
+                .map(|dependency_path| format!("{}:{}", include_path, dependency_path))
+            {
+                *depends_on = rerooted;
+            }
+        }
+        if let Resource::Group { ref mut group } = resource.resource {
+            reroot_qualified_dependencies(group, include_path);
+        }
+    }
+}
+
+/// Adds a Helm repository of an included file, unless the same repository is already defined
+fn merge_helm_repository(
+    config: &mut Config,
+    helm_repository: HelmRepository,
+    deployment_file_path: &Path,
+) -> io::Result<()> {
+    let repositories = config
+        .helm
+        .get_or_insert(Helm {
+            repositories: None,
+            registries: None,
+            defaults: None,
+        })
+        .repositories
+        .get_or_insert(Vec::new());
+    match repositories
+        .iter()
+        .find(|repository| repository.name == helm_repository.name)
+    {
+        None => repositories.push(helm_repository),
+        Some(repository) if repository.url == helm_repository.url => {}
+        Some(repository) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Configuration is invalid, helm repository \"{}\" has different urls in {:?} and the files it includes: {}, {}",
+                    helm_repository.name, deployment_file_path, repository.url, helm_repository.url
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn apply_helm_defaults(
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    helm_defaults: &HelmOptions,
+) {
+    for (_, ResourceWithDependencies { resource, .. }) in resources.iter_mut() {
+        match resource {
+            Resource::HelmRemote { helm_remote } => {
+                helm_remote.options.apply_defaults(helm_defaults)
+            }
+            Resource::HelmLocal { helm_local } => helm_local.options.apply_defaults(helm_defaults),
+            Resource::Group { group } => apply_helm_defaults(group, helm_defaults),
+            _ => {}
+        }
+    }
+}
+
+/// Adds a Helm registry of an included file, unless the same registry is already defined
+fn merge_helm_registry(
+    config: &mut Config,
+    helm_registry: HelmRegistry,
+    deployment_file_path: &Path,
+) -> io::Result<()> {
+    let registries = config
+        .helm
+        .get_or_insert(Helm {
+            repositories: None,
+            registries: None,
+            defaults: None,
+        })
+        .registries
+        .get_or_insert(Vec::new());
+    match registries
+        .iter()
+        .find(|registry| registry.host == helm_registry.host)
+    {
+        None => registries.push(helm_registry),
+        Some(registry) if *registry == helm_registry => {}
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Configuration is invalid, helm registry \"{}\" has different credentials in {:?} and the files it includes",
+                    helm_registry.host, deployment_file_path
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Removes `.` and `..` from paths, so that the same file is recognized in an include chain
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized_path.components().next_back() {
+                None | Some(Component::ParentDir) => normalized_path.push(".."),
+                _ => {
+                    normalized_path.pop();
+                }
+            },
+            other => normalized_path.push(other),
+        }
+    }
+    normalized_path
+}
+
+/// Parses a file of variables, as used with `--var-file`, in the same format as `vars`
+pub fn parse_vars_file(
+    file_reader: impl FileReader,
+    vars_file_path: &Path,
+) -> io::Result<IndexMap<String, String>> {
+    let yaml_data = file_reader.read_to_string(vars_file_path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "Unable to read variables file {:?}: {}",
+                vars_file_path, err
+            ),
+        )
+    })?;
+    serde_yaml::from_str(&yaml_data).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unable to parse variables file {:?}: {}",
+                vars_file_path, err
+            ),
+        )
+    })
+}
+
+#[test]
+fn test_normalize_path_removes_current_and_parent_dirs() {
+    assert_eq!(
+        PathBuf::from("a/c.yaml"),
+        normalize_path(Path::new("./a/b/../c.yaml"))
+    );
+    assert_eq!(
+        PathBuf::from("../../c.yaml"),
+        normalize_path(Path::new("../../c.yaml"))
+    );
+}
+
 fn integrate_deployment_file_dir_into_paths(
     resources: &mut IndexMap<String, ResourceWithDependencies>,
     deployment_file_dir: PathBuf,
@@ -59,10 +392,28 @@ fn integrate_deployment_file_dir_into_paths(
         match resource {
             Resource::Shell { .. } => {}
             Resource::Manifest { ref mut manifest } => {
+                for path in manifest
+                    .path
+                    .iter_mut()
+                    .chain(manifest.paths.iter_mut().flatten())
+                {
+                    let mut new_path = deployment_file_dir.clone();
+                    new_path.push(&path);
+
+                    *path = new_path.to_string_lossy().to_string();
+                }
+            }
+            Resource::Kustomize { ref mut kustomize } => {
+                let mut new_path = deployment_file_dir.clone();
+                new_path.push(&kustomize.path);
+
+                kustomize.path = new_path.to_string_lossy().to_string();
+            }
+            Resource::Include { ref mut include } => {
                 let mut new_path = deployment_file_dir.clone();
-                new_path.push(&manifest.path);
+                new_path.push(&include.path);
 
-                manifest.path = new_path.to_string_lossy().to_string();
+                include.path = new_path.to_string_lossy().to_string();
             }
             Resource::HelmRemote {
                 ref mut helm_remote,
@@ -93,7 +444,7 @@ fn integrate_deployment_file_dir_into_paths(
             Resource::Group { ref mut group } => {
                 integrate_deployment_file_dir_into_paths(group, deployment_file_dir.clone());
             }
-            Resource::Noop { .. } => {}
+            Resource::Wait { .. } | Resource::Noop { .. } => {}
         }
     }
 }
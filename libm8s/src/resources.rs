@@ -1,11 +1,28 @@
+#[cfg(test)]
+use crate::file_format::WaitCondition;
 use crate::file_format::{
-    HelmLocal, HelmRemote, Manifest, Resource, ResourceWithDependencies, Shell,
+    get_qualified_dependency_path, get_resource_by_path, get_resource_path, FailedReleasePolicy,
+    HelmLocal, HelmOptions, HelmRemote, Hooks, Kustomize, Manifest, Resource,
+    ResourceWithDependencies, Retry, Shell, Wait,
 };
-use indexmap::{indexmap, IndexMap};
-use log::{debug, info};
-use serde::Deserialize;
-use std::io;
+use crate::state::StateStore;
+use crate::utils::TempFile;
+#[cfg(test)]
+use indexmap::indexmap;
+use indexmap::IndexMap;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fmt, io};
 
+/// Runs the selected resources, up to `jobs` at a time. A resource starts once all the resources
+/// it depends on are done, including those its enclosing groups depend on. With a single job,
+/// resources run one after another in the same order as `plan_resources`.
 pub fn run_resources(
     resources: &IndexMap<String, ResourceWithDependencies>,
     resources_args_namespace: Option<String>,
@@ -13,7 +30,153 @@ pub fn run_resources(
     dependencies: bool,
     kubeconfig: Option<String>,
     dry_run: bool,
+    jobs: usize,
 ) -> io::Result<()> {
+    run_resources_with_options(
+        resources,
+        resources_args_namespace,
+        resources_args,
+        dependencies,
+        &RunOptions {
+            kubeconfig,
+            dry_run,
+            jobs,
+            ..RunOptions::default()
+        },
+    )
+    .result
+}
+
+/// Called with the events of a run, from the threads running the resources
+pub type EventHandler = Box<dyn Fn(&RunEvent) + Send + Sync>;
+
+/// Options changing how resources are run, as given on the command line
+#[derive(Default)]
+pub struct RunOptions {
+    pub kubeconfig: Option<String>,
+    pub dry_run: bool,
+    /// Number of resources running at the same time
+    pub jobs: usize,
+    /// Retry policy of the resources that don't set their own, nor have a group setting one
+    pub retry: Option<Retry>,
+    /// How long the whole run may take, resources still running then are terminated
+    pub timeout: Option<Duration>,
+    /// Keeps running the resources that don't depend on failed resources, rather than stopping
+    /// at the first failure
+    pub keep_going: bool,
+    /// Skips the resources whose inputs didn't change since they last succeeded, and records those
+    /// of the resources that succeed
+    pub state: Option<StateStore>,
+    /// Runs the resources with a state even when their inputs didn't change
+    pub force: bool,
+    pub on_event: Option<EventHandler>,
+}
+
+impl RunOptions {
+    fn emit(&self, event: RunEvent) {
+        if let Some(ref on_event) = self.on_event {
+            on_event(&event);
+        }
+    }
+}
+
+/// Progress of `run_resources_with_options`, serialized as JSON objects with their name in `event`
+#[derive(Debug, Serialize, PartialEq, Clone)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum RunEvent {
+    /// Resources selected for the run, in the order they would run with a single job
+    #[serde(rename_all = "camelCase")]
+    PlanResolved { resources: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    ResourceStarted {
+        resource_path: String,
+        resource_type: String,
+    },
+    /// A command of a resource is done, `exit_code` is missing when it couldn't start or was killed
+    #[serde(rename_all = "camelCase")]
+    CommandExecuted {
+        resource_path: String,
+        command: String,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+    },
+    #[serde(rename_all = "camelCase")]
+    ResourceSucceeded {
+        resource_path: String,
+        duration_ms: u64,
+    },
+    /// Sent instead of `ResourceSucceeded` when the resource didn't run, its inputs being the same
+    /// as when it last succeeded
+    #[serde(rename_all = "camelCase")]
+    ResourceUnchanged { resource_path: String },
+    #[serde(rename_all = "camelCase")]
+    ResourceFailed {
+        resource_path: String,
+        exit_code: Option<i32>,
+        error: String,
+        duration_ms: u64,
+    },
+    /// Always the last event, also sent when a resource failed
+    #[serde(rename_all = "camelCase")]
+    RunFinished { success: bool, duration_ms: u64 },
+}
+
+/// What happened to a resource during a run
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResourceStatus {
+    Ok,
+    Failed,
+    /// Not run, its inputs being the same as when it last succeeded
+    Unchanged,
+    /// Left out of the run by `--no-dependencies`, although a selected resource depends on it
+    Skipped,
+    /// Selected, but never started because another resource failed first, or with
+    /// `--keep-going` because a resource it depends on failed
+    NotRun,
+}
+
+impl fmt::Display for ResourceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ResourceStatus::Ok => "ok",
+                ResourceStatus::Failed => "failed",
+                ResourceStatus::Unchanged => "unchanged",
+                ResourceStatus::Skipped => "skipped",
+                ResourceStatus::NotRun => "not-run",
+            }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResourceReport {
+    pub resource_path: String,
+    pub resource_type: String,
+    pub status: ResourceStatus,
+    /// Time the resource took to run, missing when it didn't start
+    pub duration: Option<Duration>,
+}
+
+/// Outcome of `run_resources_with_options`, with a report of each resource even when the run failed
+#[derive(Debug)]
+pub struct RunReport {
+    pub resources: Vec<ResourceReport>,
+    pub result: io::Result<()>,
+}
+
+/// Same as `run_resources`, reporting progress to `options.on_event` and returning what happened
+/// to each resource
+pub fn run_resources_with_options(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args_namespace: Option<String>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+    options: &RunOptions,
+) -> RunReport {
     info!(
         "Running resources... resources_args = {} {:?}",
         resources_args_namespace
@@ -22,96 +185,1122 @@ pub fn run_resources(
         resources_args
     );
 
-    let resources_args_part_0 = get_resources_args_part_0(&resources_args);
-    let filtered_resources = get_filtered_resources(resources, resources_args_part_0, dependencies);
+    let selected_resources =
+        get_selected_resources(resources, resources_args.clone(), dependencies);
     debug!(
-        "Resources filtered based on config: {:?}",
-        filtered_resources
+        "Resources selected based on config: {:?}",
+        selected_resources
+    );
+
+    let scheduled_resources =
+        get_scheduled_resources(&selected_resources, resources_args_namespace.as_deref());
+
+    let run_start = Instant::now();
+    // Timeouts too long for an instant are as good as none
+    let run_deadline = options
+        .timeout
+        .and_then(|timeout| run_start.checked_add(timeout));
+    let sequential_order = match get_sequential_order(&scheduled_resources) {
+        Ok(sequential_order) => sequential_order,
+        Err(err) => {
+            return RunReport {
+                resources: vec![],
+                result: Err(err),
+            }
+        }
+    };
+    options.emit(RunEvent::PlanResolved {
+        resources: sequential_order
+            .into_iter()
+            .map(|index| scheduled_resources[index].resource_path.clone())
+            .collect(),
+    });
+
+    let resources_dependencies: Vec<Vec<usize>> = scheduled_resources
+        .iter()
+        .map(|scheduled_resource| scheduled_resource.dependencies.clone())
+        .collect();
+    let outcomes: Mutex<Vec<Option<(ResourceStatus, Duration)>>> =
+        Mutex::new(vec![None; scheduled_resources.len()]);
+    let failures = Mutex::new(Vec::new());
+    let result = crate::scheduler::run_scheduled(
+        &resources_dependencies,
+        options.jobs,
+        options.keep_going,
+        |index| {
+            let scheduled_resource = &scheduled_resources[index];
+            let resource_path = &scheduled_resource.resource_path;
+            options.emit(RunEvent::ResourceStarted {
+                resource_path: resource_path.clone(),
+                resource_type: scheduled_resource.resource.get_type_name().to_string(),
+            });
+
+            // Dependencies that ran can change what the resource deploys, like a CRD or a
+            // migration, so it runs again whatever its inputs
+            let dependency_ran = scheduled_resource.dependencies.iter().any(|&dependency| {
+                matches!(
+                    outcomes.lock().expect("Failed to read resource outcome")[dependency],
+                    Some((ResourceStatus::Ok, _))
+                )
+            });
+            let resource_start = Instant::now();
+            let result =
+                run_resource_if_changed(scheduled_resource, dependency_ran, run_deadline, options);
+            outcomes.lock().expect("Failed to record resource outcome")[index] = Some((
+                match result {
+                    Ok(true) => ResourceStatus::Ok,
+                    Ok(false) => ResourceStatus::Unchanged,
+                    Err(_) => ResourceStatus::Failed,
+                },
+                resource_start.elapsed(),
+            ));
+            match result {
+                Ok(false) => {
+                    options.emit(RunEvent::ResourceUnchanged {
+                        resource_path: resource_path.clone(),
+                    });
+                    Ok(())
+                }
+                Ok(true) => {
+                    options.emit(RunEvent::ResourceSucceeded {
+                        resource_path: resource_path.clone(),
+                        duration_ms: get_duration_ms(resource_start),
+                    });
+                    Ok(())
+                }
+                Err(err) => {
+                    options.emit(RunEvent::ResourceFailed {
+                        resource_path: resource_path.clone(),
+                        exit_code: crate::utils::get_exit_code(&err),
+                        error: err.to_string(),
+                        duration_ms: get_duration_ms(resource_start),
+                    });
+                    let message = format!("Running resource {} failed: {}", resource_path, err);
+                    if options.keep_going {
+                        warn!(
+                            "{}, keeping going with the resources that don't depend on it",
+                            message.trim_end()
+                        );
+                    }
+                    failures
+                        .lock()
+                        .expect("Failed to record resource failure")
+                        .push(message.trim_end().to_string());
+                    Err(io::Error::new(err.kind(), message))
+                }
+            }
+        },
+    );
+
+    options.emit(RunEvent::RunFinished {
+        success: result.is_ok(),
+        duration_ms: get_duration_ms(run_start),
+    });
+
+    let outcomes = outcomes
+        .into_inner()
+        .expect("Failed to read resource outcomes");
+    let outcome_by_resource_path: HashMap<&str, Option<(ResourceStatus, Duration)>> =
+        scheduled_resources
+            .iter()
+            .zip(outcomes)
+            .map(|(scheduled_resource, outcome)| {
+                (scheduled_resource.resource_path.as_str(), outcome)
+            })
+            .collect();
+
+    // Resources selected along with all their dependencies, to tell which ones were skipped
+    let selected_resources_with_dependencies =
+        get_selected_resources(resources, resources_args, true);
+    let scheduled_resources_with_dependencies = get_scheduled_resources(
+        &selected_resources_with_dependencies,
+        resources_args_namespace.as_deref(),
+    );
+    let order = get_sequential_order(&scheduled_resources_with_dependencies)
+        .unwrap_or_else(|_| (0..scheduled_resources_with_dependencies.len()).collect());
+    let resource_reports: Vec<ResourceReport> = order
+        .into_iter()
+        .map(|index| {
+            let ScheduledResource {
+                resource_path,
+                resource,
+                ..
+            } = &scheduled_resources_with_dependencies[index];
+            let (status, duration) = match outcome_by_resource_path.get(resource_path.as_str()) {
+                None => (ResourceStatus::Skipped, None),
+                Some(None) => (ResourceStatus::NotRun, None),
+                Some(Some((status, duration))) => (*status, Some(*duration)),
+            };
+            ResourceReport {
+                resource_path: resource_path.clone(),
+                resource_type: resource.get_type_name().to_string(),
+                status,
+                duration,
+            }
+        })
+        .collect();
+
+    // Only the first error would tell about the others that failed and what didn't run because of
+    // them
+    let result = match result {
+        Err(err) if options.keep_going => Err(io::Error::new(
+            err.kind(),
+            get_keep_going_error_message(
+                &resource_reports,
+                &failures
+                    .into_inner()
+                    .expect("Failed to read resource failures"),
+            ),
+        )),
+        result => result,
+    };
+
+    RunReport {
+        resources: resource_reports,
+        result,
+    }
+}
+
+fn get_keep_going_error_message(
+    resource_reports: &[ResourceReport],
+    failures: &[String],
+) -> String {
+    let get_resource_paths = |status: ResourceStatus| {
+        resource_reports
+            .iter()
+            .filter(|resource_report| resource_report.status == status)
+            .map(|resource_report| resource_report.resource_path.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+    let not_run_resource_paths = get_resource_paths(ResourceStatus::NotRun);
+
+    let mut message = format!(
+        "failed resources: {}",
+        get_resource_paths(ResourceStatus::Failed)
+    );
+    if !not_run_resource_paths.is_empty() {
+        message.push_str(
+            format!(
+                ", not run because they depend on failed resources: {}",
+                not_run_resource_paths
+            )
+            .as_str(),
+        );
+    }
+    for failure in failures {
+        message.push_str(format!("\n  - {}", failure).as_str());
+    }
+    message
+}
+
+/// Runs a resource unless the state has the hash of the same inputs from when it last succeeded
+/// and none of its dependencies ran, returns whether it ran. Shell and wait resources always run,
+/// as what they do depends on more than their inputs.
+fn run_resource_if_changed(
+    scheduled_resource: &ScheduledResource,
+    dependency_ran: bool,
+    run_deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<bool> {
+    let state = match scheduled_resource.resource {
+        Resource::Shell { .. } | Resource::Wait { .. } => None,
+        _ => options.state.as_ref(),
+    };
+    let Some(state) = state else {
+        return run_resource_with_hooks(scheduled_resource, run_deadline, options).map(|()| true);
+    };
+    let resource_path = scheduled_resource.resource_path.as_str();
+
+    let input_hash = crate::state::get_input_hash(
+        scheduled_resource.resource,
+        scheduled_resource.hooks,
+        run_deadline,
+    )?;
+    if !options.force
+        && !dependency_ran
+        && state.get_input_hash(resource_path).as_ref() == Some(&input_hash)
+    {
+        info!(
+            "Resource {} is unchanged since it last succeeded, skipping it",
+            resource_path
+        );
+        return Ok(false);
+    }
+
+    match run_resource_with_hooks(scheduled_resource, run_deadline, options) {
+        Ok(()) if options.dry_run => Ok(true),
+        Ok(()) => state
+            .set_input_hash(resource_path, input_hash)
+            .map(|()| true),
+        // A failure can leave the resource half changed, even back to the inputs it last succeeded
+        // with, so it runs again next time whatever its inputs
+        Err(err) if options.dry_run => Err(err),
+        Err(err) => {
+            if let Err(state_err) = state.remove_input_hash(resource_path) {
+                warn!("{}", state_err);
+            }
+            Err(err)
+        }
+    }
+}
+
+#[test]
+fn test_run_resource_if_changed_runs_kustomization_again_when_its_base_changed() {
+    let dir = std::env::temp_dir().join(format!("m8s-{}-kustomize-state", std::process::id()));
+    std::fs::create_dir_all(dir.join("base")).unwrap();
+    std::fs::create_dir_all(dir.join("overlay")).unwrap();
+    std::fs::write(
+        dir.join("base/kustomization.yaml"),
+        "resources:\n  - configmap.yaml\n",
+    )
+    .unwrap();
+    let config_map = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: app\ndata:\n";
+    std::fs::write(
+        dir.join("base/configmap.yaml"),
+        format!("{}  level: info\n", config_map),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("overlay/kustomization.yaml"),
+        "resources:\n  - ../base\n",
+    )
+    .unwrap();
+
+    let resource = Resource::Kustomize {
+        kustomize: Kustomize {
+            path: dir.join("overlay").to_string_lossy().to_string(),
+        },
+    };
+    let scheduled_resource = ScheduledResource {
+        resource_path: "app".to_string(),
+        resource: &resource,
+        retry: None,
+        timeout: None,
+        hooks: None,
+        dependencies: Vec::new(),
+    };
+    let state = StateStore::open(&dir.join("state.json"), Default::default()).unwrap();
+    state
+        .set_input_hash(
+            "app",
+            crate::state::get_input_hash(&resource, None, None).unwrap(),
+        )
+        .unwrap();
+    let options = RunOptions {
+        dry_run: true,
+        state: Some(state),
+        ..RunOptions::default()
+    };
+    assert!(!run_resource_if_changed(&scheduled_resource, false, None, &options).unwrap());
+
+    // The base is outside of the kustomization directory
+    std::fs::write(
+        dir.join("base/configmap.yaml"),
+        format!("{}  level: debug\n", config_map),
+    )
+    .unwrap();
+    let ran = run_resource_if_changed(&scheduled_resource, false, None, &options);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(ran.unwrap());
+}
+
+/// Runs a resource between its `pre` and `post` hooks, then its `onFailure` hook when any of them
+/// failed
+fn run_resource_with_hooks(
+    scheduled_resource: &ScheduledResource,
+    run_deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let ScheduledResource {
+        resource_path,
+        resource,
+        retry,
+        timeout,
+        hooks,
+        ..
+    } = scheduled_resource;
+    let hook_envs = get_hook_envs(resource_path, resource);
+    let run_hook =
+        |hook_name: &str, script: &Option<String>, envs: &[(String, String)]| match script {
+            None => Ok(()),
+            Some(script) => run_resource_hook(
+                resource_path,
+                hook_name,
+                script,
+                envs,
+                run_deadline,
+                options,
+            ),
+        };
+
+    let result = hooks
+        .map_or(Ok(()), |hooks| run_hook("pre", &hooks.pre, &hook_envs))
+        .and_then(|()| {
+            run_resource_with_retry(
+                resource_path,
+                resource,
+                retry.or(options.retry.as_ref()),
+                *timeout,
+                run_deadline,
+                options,
+            )
+        })
+        .and_then(|()| hooks.map_or(Ok(()), |hooks| run_hook("post", &hooks.post, &hook_envs)));
+
+    if let (Err(err), Some(hooks)) = (&result, hooks) {
+        let mut on_failure_envs = hook_envs.clone();
+        on_failure_envs.push((
+            "M8S_RESOURCE_ERROR".to_string(),
+            err.to_string().trim_end().to_string(),
+        ));
+        if let Err(on_failure_err) = run_hook("onFailure", &hooks.on_failure, &on_failure_envs) {
+            warn!("Resource {} failed, then {}", resource_path, on_failure_err);
+        }
+    }
+    result
+}
+
+/// Metadata of the resource, in the environment of its hooks
+fn get_hook_envs(resource_path: &str, resource: &Resource) -> Vec<(String, String)> {
+    let mut envs = vec![
+        ("M8S_RESOURCE_PATH".to_string(), resource_path.to_string()),
+        (
+            "M8S_RESOURCE_TYPE".to_string(),
+            resource.get_type_name().to_string(),
+        ),
+    ];
+    let release = match resource {
+        Resource::HelmRemote { helm_remote } => Some((&helm_remote.name, &helm_remote.namespace)),
+        Resource::HelmLocal { helm_local } => Some((&helm_local.name, &helm_local.namespace)),
+        _ => None,
+    };
+    if let Some((name, namespace)) = release {
+        envs.push(("M8S_RELEASE_NAME".to_string(), name.clone()));
+        envs.push(("M8S_RELEASE_NAMESPACE".to_string(), namespace.clone()));
+    }
+    envs
+}
+
+fn run_resource_hook(
+    resource_path: &str,
+    hook_name: &str,
+    script: &str,
+    envs: &[(String, String)],
+    run_deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let command = get_hook_command(script);
+    let command_start = Instant::now();
+    let result = crate::utils::run_command_with_deadline(
+        command.program.as_str(),
+        command
+            .args
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .as_slice(),
+        envs,
+        options.kubeconfig.clone(),
+        options.dry_run,
+        get_output_prefix(resource_path, options).as_deref(),
+        run_deadline,
     );
+    emit_command_executed(options, resource_path, &command, &result, command_start);
+    result.map_err(
+        |err| match (crate::utils::is_deadline_exceeded(&err), options.timeout) {
+            (true, Some(run_timeout)) => io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "{} hook timed out, the run took longer than its timeout of {}s",
+                    hook_name,
+                    run_timeout.as_secs_f64()
+                ),
+            ),
+            _ => io::Error::new(err.kind(), format!("{} hook failed: {}", hook_name, err)),
+        },
+    )
+}
+
+fn get_hook_command(script: &str) -> PlannedCommand {
+    PlannedCommand::new("bash", vec!["-c".to_string(), script.to_string()])
+}
 
-    for (resource_key, ResourceWithDependencies { resource, .. }) in filtered_resources.iter() {
-        debug!("Running resource {} = {:?}", resource_key, resource);
+/// Prefix of the output lines of a resource, as lines of concurrent resources would otherwise be
+/// impossible to tell apart
+fn get_output_prefix(resource_path: &str, options: &RunOptions) -> Option<String> {
+    if options.jobs > 1 {
+        Some(format!("[{}] ", resource_path))
+    } else {
+        None
+    }
+}
+
+fn emit_command_executed(
+    options: &RunOptions,
+    resource_path: &str,
+    command: &PlannedCommand,
+    result: &io::Result<()>,
+    command_start: Instant,
+) {
+    options.emit(RunEvent::CommandExecuted {
+        resource_path: resource_path.to_string(),
+        command: command.to_string(),
+        exit_code: match result {
+            Ok(()) => Some(0),
+            Err(err) => crate::utils::get_exit_code(err),
+        },
+        duration_ms: get_duration_ms(command_start),
+    });
+}
+
+const DEFAULT_RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Runs a resource until it succeeds or has failed as many times as its retry policy allows.
+/// Each attempt is stopped at the timeout of the resource, and neither attempts nor the delays
+/// between them go past `run_deadline`.
+fn run_resource_with_retry(
+    resource_path: &str,
+    resource: &Resource,
+    retry: Option<&Retry>,
+    timeout: Option<&String>,
+    run_deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let timeout = match timeout {
+        None => None,
+        Some(timeout) => Some(crate::utils::parse_duration(timeout.as_str())?),
+    };
+    let Some(retry) = retry else {
+        return run_resource_with_timeout(resource_path, resource, timeout, run_deadline, options);
+    };
+    let mut delay = match retry.initial_delay {
+        None => DEFAULT_RETRY_INITIAL_DELAY,
+        Some(ref initial_delay) => crate::utils::parse_duration(initial_delay.as_str())?,
+    };
+    let max_delay = match retry.max_delay {
+        None => DEFAULT_RETRY_MAX_DELAY,
+        Some(ref max_delay) => crate::utils::parse_duration(max_delay.as_str())?,
+    };
+
+    let mut attempt = 1;
+    loop {
+        info!(
+            "Running resource {}, attempt {} of {}",
+            resource_path, attempt, retry.attempts
+        );
+        match run_resource_with_timeout(resource_path, resource, timeout, run_deadline, options) {
+            Err(err)
+                if attempt < retry.attempts
+                    && run_deadline.is_none_or(|run_deadline| Instant::now() < run_deadline) =>
+            {
+                warn!(
+                    "Resource {} failed on attempt {} of {}, retrying in {}s: {}",
+                    resource_path,
+                    attempt,
+                    retry.attempts,
+                    delay.as_secs_f64(),
+                    err.to_string().trim_end()
+                );
+                match run_deadline {
+                    None => thread::sleep(delay),
+                    Some(run_deadline) => {
+                        thread::sleep(
+                            delay.min(run_deadline.saturating_duration_since(Instant::now())),
+                        );
+                        if Instant::now() >= run_deadline {
+                            return Err(err);
+                        }
+                    }
+                }
+                delay = (delay * 2).min(max_delay);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs a resource, terminating its commands once `timeout` has passed or at `run_deadline`,
+/// whichever comes first
+fn run_resource_with_timeout(
+    resource_path: &str,
+    resource: &Resource,
+    timeout: Option<Duration>,
+    run_deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    let resource_deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+    let deadline = match (resource_deadline, run_deadline) {
+        (Some(resource_deadline), Some(run_deadline)) => Some(resource_deadline.min(run_deadline)),
+        (resource_deadline, run_deadline) => resource_deadline.or(run_deadline),
+    };
+    run_resource(resource_path, resource, deadline, options).map_err(|err| {
+        if !crate::utils::is_deadline_exceeded(&err) {
+            return err;
+        }
+        let message = match (timeout, options.timeout) {
+            (Some(timeout), _) if deadline == resource_deadline => {
+                format!("timed out after {}s", timeout.as_secs_f64())
+            }
+            (_, Some(run_timeout)) => format!(
+                "timed out, the run took longer than its timeout of {}s",
+                run_timeout.as_secs_f64()
+            ),
+            _ => "timed out".to_string(),
+        };
+        io::Error::new(io::ErrorKind::TimedOut, message)
+    })
+}
+
+fn run_resource(
+    resource_path: &str,
+    resource: &Resource,
+    deadline: Option<Instant>,
+    options: &RunOptions,
+) -> io::Result<()> {
+    debug!("Running resource {} = {:?}", resource_path, resource);
+
+    let output_prefix = get_output_prefix(resource_path, options);
+    // Temporary files of the commands, deleted once they have run
+    let mut temp_files = Vec::new();
+    let commands = get_resource_commands(
+        resource,
+        options.kubeconfig.clone(),
+        options.dry_run,
+        deadline,
+        &mut temp_files,
+    )?;
+    for command in commands {
+        let command_start = Instant::now();
+        let result = match resource {
+            Resource::Wait { wait } => run_resource_wait_command(
+                &command,
+                wait,
+                options.kubeconfig.clone(),
+                options.dry_run,
+                output_prefix.as_deref(),
+                deadline,
+            ),
+            _ => crate::utils::run_command_with_deadline(
+                command.program.as_str(),
+                command
+                    .args
+                    .iter()
+                    .map(|s| s.as_str())
+                    .collect::<Vec<&str>>()
+                    .as_slice(),
+                &[],
+                options.kubeconfig.clone(),
+                options.dry_run,
+                output_prefix.as_deref(),
+                deadline,
+            ),
+        };
+        emit_command_executed(options, resource_path, &command, &result, command_start);
+        result?;
+    }
+    Ok(())
+}
+
+fn get_duration_ms(start: Instant) -> u64 {
+    start.elapsed().as_millis() as u64
+}
+
+struct ScheduledResource<'a> {
+    resource_path: String,
+    resource: &'a Resource,
+    /// Retry policy of the resource, or of the closest group setting one
+    retry: Option<&'a Retry>,
+    /// Timeout of the resource, or of the closest group setting one
+    timeout: Option<&'a String>,
+    hooks: Option<&'a Hooks>,
+    dependencies: Vec<usize>,
+}
+
+/// Flattens selected resources into the resources that run commands, in the order they run.
+/// Dependencies are indices of other scheduled resources, those that were not selected are not
+/// run, so there is nothing to wait for.
+fn get_scheduled_resources<'a>(
+    resources: &'a IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+) -> Vec<ScheduledResource<'a>> {
+    let mut scheduled_resources = Vec::new();
+    collect_flattened_resources(
+        &mut scheduled_resources,
+        resources,
+        resources_namespace,
+        None,
+        None,
+    );
+
+    let dependencies_by_resource_path =
+        crate::file_format::get_flattened_dependencies(resources, resources_namespace);
+    let index_by_resource_path: HashMap<String, usize> = scheduled_resources
+        .iter()
+        .enumerate()
+        .map(|(index, scheduled_resource)| (scheduled_resource.resource_path.clone(), index))
+        .collect();
+
+    for scheduled_resource in scheduled_resources.iter_mut() {
+        scheduled_resource.dependencies = dependencies_by_resource_path
+            [&scheduled_resource.resource_path]
+            .iter()
+            .map(|dependency_path| index_by_resource_path[dependency_path])
+            .collect();
+    }
+    scheduled_resources
+}
+
+/// Collects the resources within groups, without their dependencies. Resources inherit the retry
+/// policy and timeout of the closest group setting them.
+fn collect_flattened_resources<'a>(
+    scheduled_resources: &mut Vec<ScheduledResource<'a>>,
+    resources: &'a IndexMap<String, ResourceWithDependencies>,
+    resources_namespace: Option<&str>,
+    group_retry: Option<&'a Retry>,
+    group_timeout: Option<&'a String>,
+) {
+    for (
+        resource_key,
+        ResourceWithDependencies {
+            resource,
+            retry,
+            timeout,
+            hooks,
+            ..
+        },
+    ) in resources
+    {
+        let resource_path = get_resource_path(resources_namespace, resource_key);
+        let retry = retry.as_ref().or(group_retry);
+        let timeout = timeout.as_ref().or(group_timeout);
+        match resource {
+            Resource::Group { group } => collect_flattened_resources(
+                scheduled_resources,
+                group,
+                Some(resource_path.as_str()),
+                retry,
+                timeout,
+            ),
+            _ => scheduled_resources.push(ScheduledResource {
+                resource_path,
+                resource,
+                retry,
+                timeout,
+                hooks: hooks.as_ref(),
+                dependencies: vec![],
+            }),
+        }
+    }
+}
+
+/// Command that runs as part of a resource, with `KUBECONFIG` set when a kubeconfig is given
+#[derive(Debug, Serialize, PartialEq, Clone)]
+pub struct PlannedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl PlannedCommand {
+    fn new(program: &str, args: Vec<String>) -> PlannedCommand {
+        PlannedCommand {
+            program: program.to_string(),
+            args,
+        }
+    }
+}
+
+impl fmt::Display for PlannedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", quote_shell_arg(self.program.as_str()))?;
+        for arg in self.args.iter() {
+            write!(f, " {}", quote_shell_arg(arg.as_str()))?;
+        }
+        Ok(())
+    }
+}
+
+fn quote_shell_arg(arg: &str) -> String {
+    let is_safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if is_safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+#[test]
+fn test_planned_command_display_quotes_args_when_needed() {
+    assert_eq!(
+        "bash -c 'echo \"it'\\''s\"; exit 1' ''",
+        PlannedCommand::new(
+            "bash",
+            vec![
+                "-c".to_string(),
+                "echo \"it's\"; exit 1".to_string(),
+                "".to_string()
+            ]
+        )
+        .to_string()
+    );
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedResource {
+    pub resource_path: String,
+    pub resource_type: String,
+    pub commands: Vec<PlannedCommand>,
+}
+
+/// Resolves which resources `run_resources` would run, in which order and with which commands,
+/// without reading the cluster or decrypting files. Helm releases are planned as if they didn't
+/// exist yet. Groups are flattened into their resources, and the `pre` and `post` hooks of
+/// resources are listed with their commands.
+pub fn plan_resources(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args_namespace: Option<String>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+    kubeconfig: Option<String>,
+) -> io::Result<Vec<PlannedResource>> {
+    let selected_resources = get_selected_resources(resources, resources_args, dependencies);
+    let scheduled_resources =
+        get_scheduled_resources(&selected_resources, resources_args_namespace.as_deref());
+
+    let mut planned_resources = Vec::new();
+    for index in get_sequential_order(&scheduled_resources)? {
+        let ScheduledResource {
+            resource_path,
+            resource,
+            hooks,
+            ..
+        } = &scheduled_resources[index];
+        let pre_hook = hooks.and_then(|hooks| hooks.pre.as_deref());
+        let post_hook = hooks.and_then(|hooks| hooks.post.as_deref());
+        let mut commands: Vec<PlannedCommand> =
+            pre_hook.map(get_hook_command).into_iter().collect();
+        // Like with `--dry-run`, the cluster isn't read and nothing is decrypted
+        let mut temp_files = Vec::new();
+        let resource_commands =
+            get_resource_commands(resource, kubeconfig.clone(), true, None, &mut temp_files)?;
+        // The temporary files are deleted once the plan is returned, so their paths are replaced
+        // with what they hold
+        commands.extend(resource_commands.into_iter().map(|mut command| {
+            for arg in command.args.iter_mut() {
+                if temp_files
+                    .iter()
+                    .any(|temp_file| temp_file.path() == Path::new(arg.as_str()))
+                {
+                    *arg = "<valuesInline>".to_string();
+                }
+            }
+            command
+        }));
+        commands.extend(post_hook.map(get_hook_command));
+        planned_resources.push(PlannedResource {
+            resource_path: resource_path.clone(),
+            resource_type: resource.get_type_name().to_string(),
+            commands,
+        });
+    }
+
+    Ok(planned_resources)
+}
+
+/// Order in which `run_resources` runs the resources with a single job
+fn get_sequential_order(scheduled_resources: &[ScheduledResource]) -> io::Result<Vec<usize>> {
+    crate::scheduler::get_sequential_order(
+        scheduled_resources
+            .iter()
+            .map(|scheduled_resource| scheduled_resource.dependencies.clone())
+            .collect::<Vec<Vec<usize>>>()
+            .as_slice(),
+    )
+}
+
+fn get_resource_commands(
+    resource: &Resource,
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<PlannedCommand>> {
+    Ok(match resource {
+        Resource::Noop { noop: _ } => vec![],
+        Resource::Group { .. } => vec![],
+        // Includes are replaced with groups when parsing the deployment file
+        Resource::Include { .. } => vec![],
+        Resource::Shell { shell } => vec![get_resource_shell_command(shell)],
+        Resource::Manifest { manifest } => {
+            vec![get_resource_manifest_command(
+                manifest, dry_run, deadline, temp_files,
+            )?]
+        }
+        Resource::Kustomize { kustomize } => vec![get_resource_kustomize_command(kustomize)],
+        Resource::Wait { wait } => vec![get_resource_wait_command(wait)?],
+        Resource::HelmRemote { helm_remote } => get_resource_helm_remote_commands(
+            helm_remote,
+            kubeconfig,
+            dry_run,
+            deadline,
+            temp_files,
+        )?,
+        Resource::HelmLocal { helm_local } => {
+            get_resource_helm_local_commands(helm_local, kubeconfig, dry_run, deadline, temp_files)?
+        }
+    })
+}
+
+/// Tears down the selected resources, dependents first. Their input hashes are removed from
+/// `state`, so that the next run deploys them again.
+pub fn down_resources(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args_namespace: Option<String>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    state: Option<&StateStore>,
+) -> io::Result<()> {
+    info!(
+        "Tearing down resources... resources_args = {} {:?}",
+        resources_args_namespace
+            .clone()
+            .unwrap_or("root".to_string()),
+        resources_args
+    );
+
+    let selected_resources = get_selected_resources(resources, resources_args, dependencies);
+    debug!(
+        "Resources selected based on config: {:?}",
+        selected_resources
+    );
+    let scheduled_resources =
+        get_scheduled_resources(&selected_resources, resources_args_namespace.as_deref());
+
+    // Dependents are torn down before the resources they depend on
+    for index in get_sequential_order(&scheduled_resources)?
+        .into_iter()
+        .rev()
+    {
+        let ScheduledResource {
+            resource_path,
+            resource,
+            ..
+        } = &scheduled_resources[index];
+        debug!("Tearing down resource {} = {:?}", resource_path, resource);
         match resource {
             Resource::Noop { noop: _ } => {}
+            Resource::Group { .. } | Resource::Include { .. } | Resource::Wait { .. } => {}
             Resource::Shell { shell } => {
-                run_resource_shell(dry_run, &shell, kubeconfig.clone())?;
+                down_resource_shell(dry_run, shell, kubeconfig.clone())?;
             }
             Resource::Manifest { manifest } => {
-                run_resource_manifest(dry_run, manifest, kubeconfig.clone())?;
+                down_resource_manifest(dry_run, manifest, kubeconfig.clone())?;
+            }
+            Resource::Kustomize { kustomize } => {
+                down_resource_kustomize(dry_run, kustomize, kubeconfig.clone())?;
             }
             Resource::HelmRemote { helm_remote } => {
-                run_resource_helm_remote(dry_run, helm_remote, kubeconfig.clone())?;
+                down_resource_helm_release(
+                    dry_run,
+                    helm_remote.name.as_str(),
+                    helm_remote.namespace.as_str(),
+                    kubeconfig.clone(),
+                )?;
             }
             Resource::HelmLocal { helm_local } => {
-                run_resource_helm_local(dry_run, helm_local, kubeconfig.clone())?;
-            }
-            Resource::Group { group } => {
-                let group_namespace =
-                    get_group_namespace(resources_args_namespace.clone(), resource_key.as_str());
-
-                // When no resource args are given for a group, it means it was passed from the CLI
-                // and the user means to enable dependency resolution for the resources within this
-                // group, even if --no-dependencies was passed.
-                // In addition, in that specific case, we'll run all the resources in the group.
-                let resource_args_for_group =
-                    get_resources_args_part_1(&resources_args, resource_key.clone());
-                let dependencies = dependencies || resource_args_for_group.len() == 0;
-                let actual_resource_args_for_group = if resource_args_for_group.len() > 0 {
-                    resource_args_for_group
-                } else {
-                    group.keys().map(|s| s.to_string()).collect()
-                };
-
-                run_resources(
-                    group,
-                    group_namespace,
-                    actual_resource_args_for_group,
-                    dependencies,
-                    kubeconfig.clone(),
+                down_resource_helm_release(
                     dry_run,
+                    helm_local.name.as_str(),
+                    helm_local.namespace.as_str(),
+                    kubeconfig.clone(),
                 )?;
             }
         }
+        if let Some(state) = state.filter(|_| !dry_run) {
+            state.remove_input_hash(resource_path)?;
+        }
     }
 
     Ok(())
 }
 
-fn get_group_namespace(parent_namespace: Option<String>, resource_key: &str) -> Option<String> {
-    if let Some(ref ns) = parent_namespace {
-        Some(format!("{}:{}", ns, resource_key))
-    } else {
-        Some(resource_key.to_string())
+/// Shows what running the resources would change in the cluster, returns `true` when at least
+/// one resource would change.
+pub fn diff_resources(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args_namespace: Option<String>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+    kubeconfig: Option<String>,
+) -> io::Result<bool> {
+    info!(
+        "Diffing resources... resources_args = {} {:?}",
+        resources_args_namespace
+            .clone()
+            .unwrap_or("root".to_string()),
+        resources_args
+    );
+
+    let selected_resources = get_selected_resources(resources, resources_args, dependencies);
+    debug!(
+        "Resources selected based on config: {:?}",
+        selected_resources
+    );
+    let scheduled_resources =
+        get_scheduled_resources(&selected_resources, resources_args_namespace.as_deref());
+
+    let mut changed = false;
+    for index in get_sequential_order(&scheduled_resources)? {
+        let ScheduledResource {
+            resource_path,
+            resource,
+            ..
+        } = &scheduled_resources[index];
+        debug!("Diffing resource {} = {:?}", resource_path, resource);
+        // Temporary files of the diff, deleted once the resource is diffed
+        let mut temp_files = Vec::new();
+        let resource_changed = match resource {
+            Resource::Noop { noop: _ } => false,
+            // Groups are flattened into their resources, includes are replaced with groups when
+            // parsing the deployment file
+            Resource::Group { .. } | Resource::Include { .. } => false,
+            Resource::Wait { .. } => false,
+            Resource::Shell { .. } => {
+                info!(
+                    "Skipping diff of shell resource {}, its changes can't be previewed",
+                    resource_path
+                );
+                false
+            }
+            Resource::Manifest { manifest } => {
+                diff_resource_manifest(manifest, kubeconfig.clone())?
+            }
+            Resource::Kustomize { kustomize } => {
+                diff_resource_kustomize(kustomize, kubeconfig.clone())?
+            }
+            Resource::HelmRemote { helm_remote } => diff_resource_helm_release(
+                helm_remote.name.as_str(),
+                helm_remote.namespace.as_str(),
+                get_helm_remote_chart_args(helm_remote, false, None, &mut temp_files)?,
+                kubeconfig.clone(),
+            )?,
+            Resource::HelmLocal { helm_local } => diff_resource_helm_release(
+                helm_local.name.as_str(),
+                helm_local.namespace.as_str(),
+                get_helm_local_chart_args(helm_local, false, None, &mut temp_files)?,
+                kubeconfig.clone(),
+            )?,
+        };
+        changed = changed || resource_changed;
     }
+
+    Ok(changed)
 }
 
-#[test]
-fn test_get_group_namespace_returns_parent_ns_with_resource_key() {
-    assert_eq!(
-        "parent:this-resource",
-        get_group_namespace(Some("parent".to_string()), "this-resource").unwrap()
-    );
+/// Returns the resources selected by RESOURCES, in the order they run, with groups only containing
+/// their own selected resources. With dependencies, resources of other groups that selected
+/// resources depend on are selected too.
+pub fn get_selected_resources(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+) -> IndexMap<String, ResourceWithDependencies> {
+    let mut resources_args = resources_args;
+    loop {
+        let selected_resources = select_resources(resources, resources_args.clone(), dependencies);
+        if !dependencies {
+            return selected_resources;
+        }
+
+        let missing_dependency_paths: Vec<String> = get_qualified_dependencies(&selected_resources)
+            .into_iter()
+            .filter(|dependency_path| {
+                get_resource_by_path(&selected_resources, dependency_path).is_none()
+                    && get_resource_by_path(resources, dependency_path).is_some()
+            })
+            .collect();
+        if missing_dependency_paths.is_empty() {
+            return selected_resources;
+        }
+        resources_args.extend(missing_dependency_paths);
+    }
 }
 
-#[test]
-fn test_get_group_namespace_returns_resource_key_if_empty_parent() {
-    assert_eq!(
-        "this-resource",
-        get_group_namespace(None, "this-resource").unwrap()
-    );
+fn get_qualified_dependencies(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+) -> Vec<String> {
+    let mut qualified_dependencies = Vec::new();
+    for resource in resources.values() {
+        qualified_dependencies.extend(
+            resource
+                .depends_on
+                .iter()
+                .flatten()
+                .filter_map(|depends_on| get_qualified_dependency_path(depends_on))
+                .map(|dependency_path| dependency_path.to_string()),
+        );
+        if let Resource::Group { group } = &resource.resource {
+            qualified_dependencies.extend(get_qualified_dependencies(group));
+        }
+    }
+    qualified_dependencies
 }
 
-fn get_resources_args_part_0(resources_args: &Vec<String>) -> Vec<String> {
+fn select_resources(
+    resources: &IndexMap<String, ResourceWithDependencies>,
+    resources_args: Vec<String>,
+    dependencies: bool,
+) -> IndexMap<String, ResourceWithDependencies> {
+    let resources_args_part_0 = get_resources_args_part_0(&resources_args);
+    let mut filtered_resources =
+        get_filtered_resources(resources, resources_args_part_0, dependencies);
+
+    for (resource_key, ResourceWithDependencies { resource, .. }) in filtered_resources.iter_mut() {
+        if let Resource::Group { group } = resource {
+            let (resource_args_for_group, dependencies) =
+                get_group_resources_args(&resources_args, resource_key, group, dependencies);
+            *group = select_resources(group, resource_args_for_group, dependencies);
+        }
+    }
+
+    filtered_resources
+}
+
+fn get_group_resources_args(
+    resources_args: &[String],
+    resource_key: &str,
+    group: &IndexMap<String, ResourceWithDependencies>,
+    dependencies: bool,
+) -> (Vec<String>, bool) {
+    // When no resource args are given for a group, it means it was passed from the CLI
+    // and the user means to enable dependency resolution for the resources within this
+    // group, even if --no-dependencies was passed.
+    // In addition, in that specific case, we'll run all the resources in the group.
+    let resource_args_for_group = get_resources_args_part_1(resources_args, resource_key);
+    if resource_args_for_group.is_empty() {
+        (group.keys().map(|s| s.to_string()).collect(), true)
+    } else {
+        (resource_args_for_group, dependencies)
+    }
+}
+
+fn get_resources_args_part_0(resources_args: &[String]) -> Vec<String> {
     resources_args
-        .clone()
         .iter()
         .map(|ua| {
-            return ua
-                .splitn(2, ":")
+            ua.splitn(2, ":")
                 .collect::<Vec<&str>>()
-                .get(0)
+                .first()
                 .unwrap()
-                .to_string();
+                .to_string()
         })
         .collect()
 }
@@ -120,11 +1309,11 @@ fn get_resources_args_part_0(resources_args: &Vec<String>) -> Vec<String> {
 fn test_get_resources_args_part_0_returns_part_before_colon() {
     assert_eq!(
         vec!["a".to_string(), "b".to_string()],
-        get_resources_args_part_0(&vec!["a".to_string(), "b:c".to_string()])
+        get_resources_args_part_0(&["a".to_string(), "b:c".to_string()])
     );
 }
 
-fn get_resources_args_part_1(resources_args: &Vec<String>, resource_key: String) -> Vec<String> {
+fn get_resources_args_part_1(resources_args: &[String], resource_key: &str) -> Vec<String> {
     let resource_key_group_prefix = format!("{}:", resource_key);
     resources_args
         .iter()
@@ -144,15 +1333,15 @@ fn test_get_resources_args_part_1_returns_part_after_colon_or_nothing() {
     assert_eq!(
         vec!["c".to_string()],
         get_resources_args_part_1(
-            &vec!["a".to_string(), "b:c".to_string(), "d:e".to_string()],
-            "b".to_string()
+            &["a".to_string(), "b:c".to_string(), "d:e".to_string()],
+            "b"
         )
     );
     assert_eq!(
         Vec::<String>::new(),
         get_resources_args_part_1(
-            &vec!["a".to_string(), "b:c".to_string(), "d:e".to_string()],
-            "a".to_string()
+            &["a".to_string(), "b:c".to_string(), "d:e".to_string()],
+            "a"
         )
     );
 }
@@ -165,13 +1354,13 @@ fn reorder_resources_from_dependencies(
         &resources,
         output.keys().collect::<Vec<&String>>().as_slice(),
     ) {
-        for (resource_key, ResourceWithDependencies { depends_on, .. }) in resources.iter() {
-            let depends_on: Vec<String> = depends_on.clone().unwrap_or(Vec::new());
+        for (resource_key, resource) in resources.iter() {
+            let depends_on = resource.get_sibling_dependencies();
             let missing_dependencies = depends_on
                 .iter()
                 .filter(|item| !output.keys().collect::<Vec<&String>>().contains(item))
                 .collect::<Vec<&String>>();
-            if missing_dependencies.len() > 0 {
+            if !missing_dependencies.is_empty() {
                 debug!(
                     "Skipping resource \"{}\", waiting for dependencies: {:?}",
                     resource_key, missing_dependencies
@@ -194,18 +1383,27 @@ fn test_reorder_resources_from_dependencies_returns_resources_in_right_order() {
                 noop: "".to_string(),
             },
             depends_on: Some(vec!["a".to_string()]),
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
         "a".to_string() => ResourceWithDependencies {
             resource: Resource::Noop {
                 noop: "".to_string(),
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
         "c".to_string() => ResourceWithDependencies {
             resource: Resource::Noop {
                 noop: "".to_string(),
             },
             depends_on: Some(vec!["b".to_string()]),
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
     };
 
@@ -216,18 +1414,27 @@ fn test_reorder_resources_from_dependencies_returns_resources_in_right_order() {
                     noop: "".to_string(),
                 },
                 depends_on: None,
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
             "b".to_string() => ResourceWithDependencies {
                 resource: Resource::Noop {
                     noop: "".to_string(),
                 },
                 depends_on: Some(vec!["a".to_string()]),
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
             "c".to_string() => ResourceWithDependencies {
                 resource: Resource::Noop {
                     noop: "".to_string(),
                 },
                 depends_on: Some(vec!["b".to_string()]),
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
         }
         .as_slice(),
@@ -242,10 +1449,8 @@ fn get_filtered_resources(
 ) -> IndexMap<String, ResourceWithDependencies> {
     let mut dependencies_by_resource_key = IndexMap::new();
     for (resource_key, resource) in resources.iter() {
-        dependencies_by_resource_key.insert(
-            resource_key.clone(),
-            resource.depends_on.clone().unwrap_or(Vec::new()),
-        );
+        dependencies_by_resource_key
+            .insert(resource_key.clone(), resource.get_sibling_dependencies());
     }
 
     let mut filtered_resources = IndexMap::new();
@@ -284,24 +1489,36 @@ fn test_get_filtered_resources_returns_resources_recursively_based_on_dependenci
                 noop: "".to_string(),
             },
             depends_on: Some(vec!["a".to_string()]),
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
         "a".to_string() => ResourceWithDependencies {
             resource: Resource::Noop {
                 noop: "".to_string(),
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
         "c".to_string() => ResourceWithDependencies {
             resource: Resource::Noop {
                 noop: "".to_string(),
             },
             depends_on: Some(vec!["b".to_string()]),
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
         "d".to_string() => ResourceWithDependencies {
             resource: Resource::Noop {
                 noop: "".to_string(),
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         },
     };
 
@@ -312,18 +1529,27 @@ fn test_get_filtered_resources_returns_resources_recursively_based_on_dependenci
                     noop: "".to_string(),
                 },
                 depends_on: None,
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
             "b".to_string() => ResourceWithDependencies {
                 resource: Resource::Noop {
                     noop: "".to_string(),
                 },
                 depends_on: Some(vec!["a".to_string()]),
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
             "c".to_string() => ResourceWithDependencies {
                 resource: Resource::Noop {
                     noop: "".to_string(),
                 },
                 depends_on: Some(vec!["b".to_string()]),
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
         }
         .as_slice(),
@@ -337,6 +1563,9 @@ fn test_get_filtered_resources_returns_resources_recursively_based_on_dependenci
                     noop: "".to_string(),
                 },
                 depends_on: Some(vec!["b".to_string()]),
+                retry: None,
+                hooks: None,
+                timeout: None,
             },
         }
         .as_slice(),
@@ -350,8 +1579,7 @@ fn has_pending_resources(
 ) -> bool {
     let next_resource_not_yet_ran = resources
         .iter()
-        .filter(|(resource_key, _)| !resource_keys_done.contains(resource_key))
-        .next();
+        .find(|(resource_key, _)| !resource_keys_done.contains(resource_key));
     next_resource_not_yet_ran.is_some()
 }
 
@@ -359,160 +1587,875 @@ fn has_pending_resources(
 struct HelmRelease {
     name: String,
     namespace: String,
+    status: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct HelmRevision {
+    revision: u32,
+    status: String,
 }
 
-fn helm_release_exists(
+/// Returns the release with its status, whatever the status is
+fn get_helm_release(
     name: &str,
     namespace: &str,
     kubeconfig: Option<String>,
     dry_run: bool,
-) -> io::Result<bool> {
-    let mut command = std::process::Command::new("helm");
-    command
-        .arg("list")
-        .arg("--namespace")
-        .arg(namespace)
-        .arg("--output")
-        .arg("yaml");
-
-    if let Some(c) = kubeconfig {
-        command.env("KUBECONFIG", c.to_string());
-    }
-
+    deadline: Option<Instant>,
+) -> io::Result<Option<HelmRelease>> {
     if dry_run {
-        return Ok(false);
+        return Ok(None);
     }
 
-    let output = command.output()?;
+    // Without `--all`, failed and pending releases are left out, and without `--max 0`, releases
+    // past the 256th are
+    let output = crate::utils::run_command_with_captured_stdout(
+        "helm",
+        &[
+            "list",
+            "--namespace",
+            namespace,
+            "--all",
+            "--max",
+            "0",
+            "--output",
+            "yaml",
+        ],
+        kubeconfig,
+        deadline,
+    )?;
 
-    let helm_releases: Vec<HelmRelease> = serde_yaml::from_str(
-        String::from_utf8_lossy(output.stdout.as_slice()).as_ref(),
-    )
-    .map_err(|err| {
+    let helm_releases: Vec<HelmRelease> = serde_yaml::from_str(output.as_str()).map_err(|err| {
         io::Error::new(
             io::ErrorKind::InvalidData,
             format!("Could not read helm releases: {}", err),
         )
     })?;
 
-    Ok(helm_releases.contains(&HelmRelease {
-        name: name.to_string(),
-        namespace: namespace.to_string(),
-    }))
+    Ok(helm_releases
+        .into_iter()
+        .find(|helm_release| helm_release.name == name && helm_release.namespace == namespace))
 }
 
-fn run_resource_helm_local(
-    dry_run: bool,
-    helm_local: &HelmLocal,
+/// Returns the latest revision of the release that was deployed successfully
+fn get_helm_last_deployed_revision(
+    name: &str,
+    namespace: &str,
     kubeconfig: Option<String>,
-) -> Result<(), io::Error> {
-    let already_installed = helm_release_exists(
-        helm_local.name.as_str(),
-        helm_local.namespace.as_str(),
-        kubeconfig.clone(),
-        dry_run,
+    deadline: Option<Instant>,
+) -> io::Result<Option<u32>> {
+    let output = crate::utils::run_command_with_captured_stdout(
+        "helm",
+        &[
+            "history",
+            name,
+            "--namespace",
+            namespace,
+            "--output",
+            "yaml",
+        ],
+        kubeconfig,
+        deadline,
     )?;
 
-    let mut args = Vec::<String>::new();
-    args.push(
-        if already_installed {
-            "upgrade"
-        } else {
-            "install"
+    let helm_revisions: Vec<HelmRevision> =
+        serde_yaml::from_str(output.as_str()).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not read helm release history: {}", err),
+            )
+        })?;
+
+    Ok(helm_revisions
+        .into_iter()
+        .filter(|helm_revision| {
+            helm_revision.status == "deployed" || helm_revision.status == "superseded"
+        })
+        .map(|helm_revision| helm_revision.revision)
+        .max())
+}
+
+/// Commands installing or upgrading a release with `args`, depending on the status of the
+/// release. Releases stuck in a failed or pending state are handled according to the policy.
+fn get_helm_release_commands(
+    name: &str,
+    namespace: &str,
+    helm_release: Option<HelmRelease>,
+    failed_release_policy: FailedReleasePolicy,
+    args: Vec<String>,
+    kubeconfig: Option<String>,
+    deadline: Option<Instant>,
+) -> io::Result<Vec<PlannedCommand>> {
+    let install = |mut command_args: Vec<String>| {
+        command_args.extend(args.clone());
+        PlannedCommand::new("helm", command_args)
+    };
+
+    let Some(helm_release) = helm_release else {
+        return Ok(vec![install(vec!["install".to_string()])]);
+    };
+    match helm_release.status.as_str() {
+        "deployed" | "superseded" => Ok(vec![install(vec!["upgrade".to_string()])]),
+        // Kept with `helm uninstall --keep-history`, the name can be reused by replacing it
+        "uninstalled" => Ok(vec![install(vec![
+            "install".to_string(),
+            "--replace".to_string(),
+        ])]),
+        status => match failed_release_policy {
+            FailedReleasePolicy::Error => Err(io::Error::other(format!(
+                "Helm release {} in namespace {} is {}, set failedReleasePolicy to rollback or reinstall, or fix the release by hand",
+                name, namespace, status
+            ))),
+            FailedReleasePolicy::Rollback => {
+                let Some(revision) = get_helm_last_deployed_revision(name, namespace, kubeconfig, deadline)?
+                else {
+                    return Err(io::Error::other(format!(
+                        "Helm release {} in namespace {} is {} and was never deployed, it can't be rolled back",
+                        name, namespace, status
+                    )));
+                };
+                Ok(vec![
+                    PlannedCommand::new(
+                        "helm",
+                        vec![
+                            "rollback".to_string(),
+                            name.to_string(),
+                            revision.to_string(),
+                            "--namespace".to_string(),
+                            namespace.to_string(),
+                        ],
+                    ),
+                    install(vec!["upgrade".to_string()]),
+                ])
+            }
+            FailedReleasePolicy::Reinstall => Ok(vec![
+                PlannedCommand::new(
+                    "helm",
+                    vec![
+                        "uninstall".to_string(),
+                        name.to_string(),
+                        "--namespace".to_string(),
+                        namespace.to_string(),
+                        "--wait".to_string(),
+                    ],
+                ),
+                install(vec!["install".to_string()]),
+            ]),
+        },
+    }
+}
+
+#[cfg(test)]
+fn create_helm_release(status: &str) -> Option<HelmRelease> {
+    Some(HelmRelease {
+        name: "my-release".to_string(),
+        namespace: "default".to_string(),
+        status: status.to_string(),
+    })
+}
+
+#[test]
+fn test_get_helm_release_commands_installs_or_upgrades_depending_on_status() {
+    let to_strings = |commands: Vec<PlannedCommand>| {
+        commands
+            .iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>()
+    };
+    let get_commands = |helm_release| {
+        get_helm_release_commands(
+            "my-release",
+            "default",
+            helm_release,
+            FailedReleasePolicy::Error,
+            vec!["my-release".to_string(), "chart".to_string()],
+            None,
+            None,
+        )
+        .map(to_strings)
+    };
+
+    assert_eq!(
+        vec!["helm install my-release chart"],
+        get_commands(None).unwrap()
+    );
+    assert_eq!(
+        vec!["helm upgrade my-release chart"],
+        get_commands(create_helm_release("deployed")).unwrap()
+    );
+    assert_eq!(
+        vec!["helm install --replace my-release chart"],
+        get_commands(create_helm_release("uninstalled")).unwrap()
+    );
+    assert_eq!(
+        "Helm release my-release in namespace default is pending-upgrade, set failedReleasePolicy to rollback or reinstall, or fix the release by hand",
+        get_commands(create_helm_release("pending-upgrade"))
+            .err()
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_get_helm_release_commands_reinstalls_failed_release() {
+    assert_eq!(
+        vec![
+            "helm uninstall my-release --namespace default --wait",
+            "helm install my-release chart"
+        ],
+        get_helm_release_commands(
+            "my-release",
+            "default",
+            create_helm_release("failed"),
+            FailedReleasePolicy::Reinstall,
+            vec!["my-release".to_string(), "chart".to_string()],
+            None,
+            None,
+        )
+        .unwrap()
+        .iter()
+        .map(|command| command.to_string())
+        .collect::<Vec<String>>()
+    );
+}
+
+fn get_helm_local_chart_args(
+    helm_local: &HelmLocal,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    let mut args = vec![
+        helm_local.name.to_string(),
+        helm_local.chart_path.to_string(),
+        "--namespace".to_string(),
+        helm_local.namespace.to_string(),
+    ];
+
+    let values = match helm_local.values {
+        None => None,
+        Some(ref values) => Some(crate::sops::get_decrypted_paths(
+            values.clone(),
+            helm_local.sops,
+            dry_run,
+            deadline,
+            temp_files,
+        )?),
+    };
+    args.extend(get_helm_values_args(
+        &values,
+        &helm_local.values_inline,
+        &helm_local.set,
+        &helm_local.set_string,
+        temp_files,
+    )?);
+
+    Ok(args)
+}
+
+fn get_helm_remote_chart_args(
+    helm_remote: &HelmRemote,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    let mut args = vec![
+        helm_remote.name.to_string(),
+        helm_remote.chart_name.to_string(),
+        "--version".to_string(),
+        helm_remote.chart_version.to_string(),
+        "--namespace".to_string(),
+        helm_remote.namespace.to_string(),
+    ];
+
+    let values = match helm_remote.values {
+        None => None,
+        Some(ref values) => Some(crate::sops::get_decrypted_paths(
+            values.clone(),
+            helm_remote.sops,
+            dry_run,
+            deadline,
+            temp_files,
+        )?),
+    };
+    args.extend(get_helm_values_args(
+        &values,
+        &helm_remote.values_inline,
+        &helm_remote.set,
+        &helm_remote.set_string,
+        temp_files,
+    )?);
+
+    Ok(args)
+}
+
+fn get_helm_options_args(helm_options: &HelmOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    for (flag, enabled) in [
+        ("--create-namespace", helm_options.create_namespace),
+        ("--wait", helm_options.wait),
+        ("--wait-for-jobs", helm_options.wait_for_jobs),
+        ("--atomic", helm_options.atomic),
+    ] {
+        if enabled == Some(true) {
+            args.push(flag.to_string());
         }
-        .to_string(),
+    }
+    if let Some(ref timeout) = helm_options.timeout {
+        args.push("--timeout".to_string());
+        args.push(timeout.clone());
+    }
+    if let Some(ref description) = helm_options.description {
+        args.push("--description".to_string());
+        args.push(description.clone());
+    }
+    args
+}
+
+#[test]
+fn test_get_helm_options_args_passes_enabled_options() {
+    assert_eq!(
+        vec![
+            "--create-namespace",
+            "--wait",
+            "--timeout",
+            "10m",
+            "--description",
+            "Deployed by m8s",
+        ],
+        get_helm_options_args(&HelmOptions {
+            create_namespace: Some(true),
+            wait: Some(true),
+            wait_for_jobs: None,
+            timeout: Some("10m".to_string()),
+            atomic: Some(false),
+            description: Some("Deployed by m8s".to_string()),
+        })
     );
-    args.push(helm_local.name.to_string());
-    args.push(helm_local.chart_path.to_string());
-    args.push("--namespace".to_string());
-    args.push(helm_local.namespace.to_string());
+}
 
-    for values_file in helm_local.values.clone().unwrap_or(Vec::new()).as_slice() {
+/// Arguments passing values to Helm, from the lowest to the highest precedence: values files,
+/// inline values, `--set` and `--set-string`. Inline values are written to a temporary file,
+/// added to `temp_files` so that it outlives the command.
+fn get_helm_values_args(
+    values: &Option<Vec<String>>,
+    values_inline: &Option<IndexMap<String, serde_json::Value>>,
+    set: &Option<IndexMap<String, String>>,
+    set_string: &Option<IndexMap<String, String>>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    let mut args = Vec::new();
+
+    for values_file in values.iter().flatten() {
         args.push("-f".to_string());
         args.push(values_file.clone());
     }
 
-    crate::utils::run_command_with_piped_stdio(
-        "helm",
-        args.iter()
-            .map(|s| s.as_str())
-            .collect::<Vec<&str>>()
-            .as_slice(),
+    if let Some(values_inline) = values_inline {
+        let content = serde_yaml::to_string(values_inline).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Could not write inline values: {}", err),
+            )
+        })?;
+        let temp_file = TempFile::create("values.yaml", content.as_str())?;
+        args.push("-f".to_string());
+        args.push(temp_file.path().to_string_lossy().to_string());
+        temp_files.push(temp_file);
+    }
+
+    for (key, value) in set.iter().flatten() {
+        args.push("--set".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+
+    for (key, value) in set_string.iter().flatten() {
+        args.push("--set-string".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+
+    Ok(args)
+}
+
+#[test]
+fn test_get_helm_values_args_orders_values_by_precedence() {
+    let mut temp_files = Vec::new();
+    let args = get_helm_values_args(
+        &Some(vec!["values.yaml".to_string()]),
+        &Some(indexmap! {
+            "replicaCount".to_string() => serde_json::json!(3),
+        }),
+        &Some(indexmap! {
+            "image.tag".to_string() => "1.2.3".to_string(),
+        }),
+        &Some(indexmap! {
+            "podAnnotations.version".to_string() => "1.10".to_string(),
+        }),
+        &mut temp_files,
+    )
+    .unwrap();
+
+    let values_inline_path = temp_files[0].path().to_string_lossy().to_string();
+    assert_eq!(
+        vec![
+            "-f",
+            "values.yaml",
+            "-f",
+            values_inline_path.as_str(),
+            "--set",
+            "image.tag=1.2.3",
+            "--set-string",
+            "podAnnotations.version=1.10",
+        ],
+        args
+    );
+    assert_eq!(
+        "replicaCount: 3\n",
+        std::fs::read_to_string(temp_files[0].path()).unwrap()
+    );
+}
+
+fn get_resource_helm_local_commands(
+    helm_local: &HelmLocal,
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<PlannedCommand>> {
+    let helm_release = get_helm_release(
+        helm_local.name.as_str(),
+        helm_local.namespace.as_str(),
         kubeconfig.clone(),
         dry_run,
+        deadline,
     )?;
-    Ok(())
+
+    let mut args = get_helm_local_chart_args(helm_local, dry_run, deadline, temp_files)?;
+    args.extend(get_helm_options_args(&helm_local.options));
+
+    get_helm_release_commands(
+        helm_local.name.as_str(),
+        helm_local.namespace.as_str(),
+        helm_release,
+        helm_local.failed_release_policy.unwrap_or_default(),
+        args,
+        kubeconfig,
+        deadline,
+    )
 }
 
-fn run_resource_helm_remote(
-    dry_run: bool,
+fn get_resource_helm_remote_commands(
     helm_remote: &HelmRemote,
     kubeconfig: Option<String>,
-) -> Result<(), io::Error> {
-    let already_installed = helm_release_exists(
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<PlannedCommand>> {
+    let helm_release = get_helm_release(
         helm_remote.name.as_str(),
         helm_remote.namespace.as_str(),
         kubeconfig.clone(),
         dry_run,
+        deadline,
     )?;
 
-    let mut args = Vec::<String>::new();
-    args.push(
-        if already_installed {
-            "upgrade"
-        } else {
-            "install"
+    let mut args = get_helm_remote_chart_args(helm_remote, dry_run, deadline, temp_files)?;
+    args.extend(get_helm_options_args(&helm_remote.options));
+
+    get_helm_release_commands(
+        helm_remote.name.as_str(),
+        helm_remote.namespace.as_str(),
+        helm_release,
+        helm_remote.failed_release_policy.unwrap_or_default(),
+        args,
+        kubeconfig,
+        deadline,
+    )
+}
+
+fn get_resource_manifest_command(
+    manifest: &Manifest,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<PlannedCommand> {
+    let mut args = vec!["apply".to_string()];
+    args.extend(get_manifest_files_args(
+        manifest,
+        manifest.get_files()?,
+        dry_run,
+        deadline,
+        temp_files,
+    )?);
+    Ok(PlannedCommand::new("kubectl", args))
+}
+
+/// Arguments passing the files to kubectl, decrypted when they are encrypted with `sops`
+fn get_manifest_files_args(
+    manifest: &Manifest,
+    files: Vec<String>,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    Ok(
+        crate::sops::get_decrypted_paths(files, manifest.sops, dry_run, deadline, temp_files)?
+            .into_iter()
+            .flat_map(|file| ["-f".to_string(), file])
+            .collect(),
+    )
+}
+
+fn get_resource_kustomize_command(kustomize: &Kustomize) -> PlannedCommand {
+    PlannedCommand::new(
+        "kubectl",
+        vec![
+            "apply".to_string(),
+            "-k".to_string(),
+            kustomize.path.clone(),
+        ],
+    )
+}
+
+#[test]
+fn test_get_resource_kustomize_command_applies_directory() {
+    assert_eq!(
+        "kubectl apply -k overlays/production",
+        get_resource_kustomize_command(&Kustomize {
+            path: "overlays/production".to_string()
+        })
+        .to_string()
+    );
+}
+
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_WAIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+fn get_wait_durations(wait: &Wait) -> io::Result<(Duration, Duration)> {
+    let timeout = match wait.timeout {
+        None => DEFAULT_WAIT_TIMEOUT,
+        Some(ref timeout) => crate::utils::parse_duration(timeout.as_str())?,
+    };
+    let poll_interval = match wait.poll_interval {
+        None => DEFAULT_WAIT_POLL_INTERVAL,
+        Some(ref poll_interval) => crate::utils::parse_duration(poll_interval.as_str())?,
+    };
+    Ok((timeout, poll_interval))
+}
+
+/// Command checking the condition of a wait once, giving up after the poll interval
+fn get_resource_wait_command(wait: &Wait) -> io::Result<PlannedCommand> {
+    let (_, poll_interval) = get_wait_durations(wait)?;
+
+    let mut args = Vec::new();
+    if let Some(ref rollout) = wait.rollout {
+        args.extend(["rollout".to_string(), "status".to_string(), rollout.clone()]);
+    } else if let Some(ref condition) = wait.condition {
+        args.extend([
+            "wait".to_string(),
+            condition.object.clone(),
+            format!("--for=condition={}", condition.condition),
+        ]);
+        if let Some(ref selector) = condition.selector {
+            args.extend(["--selector".to_string(), selector.clone()]);
         }
-        .to_string(),
+    } else if let Some(ref crd) = wait.crd {
+        args.extend([
+            "wait".to_string(),
+            format!("crd/{}", crd),
+            "--for=condition=Established".to_string(),
+        ]);
+    }
+    // CRDs are cluster-wide, their namespace is ignored
+    if let (Some(ref namespace), None) = (&wait.namespace, &wait.crd) {
+        args.extend(["--namespace".to_string(), namespace.clone()]);
+    }
+    args.push(format!("--timeout={}s", poll_interval.as_secs().max(1)));
+
+    Ok(PlannedCommand::new("kubectl", args))
+}
+
+#[test]
+fn test_get_resource_wait_command_checks_condition() {
+    assert_eq!(
+        "kubectl wait pods --for=condition=Ready --selector app=web --namespace web --timeout=10s",
+        get_resource_wait_command(&Wait {
+            rollout: None,
+            condition: Some(WaitCondition {
+                object: "pods".to_string(),
+                selector: Some("app=web".to_string()),
+                condition: "Ready".to_string(),
+            }),
+            crd: None,
+            namespace: Some("web".to_string()),
+            timeout: None,
+            poll_interval: Some("10s".to_string()),
+        })
+        .unwrap()
+        .to_string()
     );
-    args.push(helm_remote.name.to_string());
-    args.push(helm_remote.chart_name.to_string());
-    args.push("--version".to_string());
-    args.push(helm_remote.chart_version.to_string());
-    args.push("--namespace".to_string());
-    args.push(helm_remote.namespace.to_string());
-
-    for values_file in helm_remote.values.clone().unwrap_or(Vec::new()).as_slice() {
-        args.push("-f".to_string());
-        args.push(values_file.clone());
+    assert_eq!(
+        "kubectl wait crd/certificates.cert-manager.io --for=condition=Established --timeout=5s",
+        get_resource_wait_command(&Wait {
+            rollout: None,
+            condition: None,
+            crd: Some("certificates.cert-manager.io".to_string()),
+            namespace: None,
+            timeout: None,
+            poll_interval: None,
+        })
+        .unwrap()
+        .to_string()
+    );
+}
+
+/// Runs the command of a wait until it succeeds, objects that don't exist yet failing right away
+/// rather than being waited for by kubectl
+fn run_resource_wait_command(
+    command: &PlannedCommand,
+    wait: &Wait,
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    output_prefix: Option<&str>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let (timeout, poll_interval) = get_wait_durations(wait)?;
+    let args = command
+        .args
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>();
+
+    let start = Instant::now();
+    loop {
+        let attempt_start = Instant::now();
+        let err = match crate::utils::run_command_with_deadline(
+            command.program.as_str(),
+            args.as_slice(),
+            &[],
+            kubeconfig.clone(),
+            dry_run,
+            output_prefix,
+            deadline,
+        ) {
+            Ok(()) => return Ok(()),
+            Err(err) if crate::utils::is_deadline_exceeded(&err) => return Err(err),
+            Err(err) => err,
+        };
+        if start.elapsed() + poll_interval > timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "condition still not met after {}s: {}",
+                    timeout.as_secs(),
+                    err
+                ),
+            ));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() + poll_interval >= deadline) {
+            return Err(crate::utils::DeadlineExceededError.into());
+        }
+        debug!("Condition not met yet, checking again: {}", err);
+        thread::sleep(poll_interval.saturating_sub(attempt_start.elapsed()));
     }
+}
 
+fn get_resource_shell_command(shell: &Shell) -> PlannedCommand {
+    PlannedCommand::new("bash", vec!["-c".to_string(), shell.input.clone()])
+}
+
+fn down_resource_helm_release(
+    dry_run: bool,
+    name: &str,
+    namespace: &str,
+    kubeconfig: Option<String>,
+) -> Result<(), io::Error> {
     crate::utils::run_command_with_piped_stdio(
         "helm",
-        args.iter()
-            .map(|s| s.as_str())
-            .collect::<Vec<&str>>()
-            .as_slice(),
+        &[
+            "uninstall",
+            name,
+            "--namespace",
+            namespace,
+            "--ignore-not-found",
+        ],
         kubeconfig,
         dry_run,
     )?;
     Ok(())
 }
 
-fn run_resource_manifest(
+fn down_resource_manifest(
     dry_run: bool,
     manifest: &Manifest,
     kubeconfig: Option<String>,
 ) -> Result<(), io::Error> {
+    // Files are deleted in the reverse order they are applied in
+    let mut files = manifest.get_files()?;
+    files.reverse();
+    let mut temp_files = Vec::new();
+    let mut args = vec!["delete".to_string(), "--ignore-not-found".to_string()];
+    args.extend(get_manifest_files_args(
+        manifest,
+        files,
+        dry_run,
+        None,
+        &mut temp_files,
+    )?);
     crate::utils::run_command_with_piped_stdio(
         "kubectl",
-        &["apply", "-f", manifest.path.as_str()],
+        args.iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .as_slice(),
         kubeconfig,
         dry_run,
     )?;
     Ok(())
 }
 
-fn run_resource_shell(
+fn down_resource_kustomize(
     dry_run: bool,
-    shell: &&Shell,
+    kustomize: &Kustomize,
     kubeconfig: Option<String>,
 ) -> Result<(), io::Error> {
     crate::utils::run_command_with_piped_stdio(
-        "bash",
-        &["-c", shell.input.as_str()],
+        "kubectl",
+        &[
+            "delete",
+            "--ignore-not-found",
+            "-k",
+            kustomize.path.as_str(),
+        ],
         kubeconfig,
         dry_run,
     )?;
     Ok(())
 }
+
+fn down_resource_shell(
+    dry_run: bool,
+    shell: &Shell,
+    kubeconfig: Option<String>,
+) -> Result<(), io::Error> {
+    match shell.teardown {
+        None => {
+            debug!("Skipping shell resource, no teardown command configured");
+        }
+        Some(ref teardown) => {
+            crate::utils::run_command_with_piped_stdio(
+                "bash",
+                &["-c", teardown.as_str()],
+                kubeconfig,
+                dry_run,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn diff_resource_manifest(manifest: &Manifest, kubeconfig: Option<String>) -> io::Result<bool> {
+    let mut temp_files = Vec::new();
+    let mut args = vec!["diff".to_string()];
+    args.extend(get_manifest_files_args(
+        manifest,
+        manifest.get_files()?,
+        false,
+        None,
+        &mut temp_files,
+    )?);
+    crate::utils::run_diff_command_with_piped_stdio(
+        "kubectl",
+        args.iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .as_slice(),
+        kubeconfig,
+    )
+}
+
+fn diff_resource_kustomize(kustomize: &Kustomize, kubeconfig: Option<String>) -> io::Result<bool> {
+    crate::utils::run_diff_command_with_piped_stdio(
+        "kubectl",
+        &["diff", "-k", kustomize.path.as_str()],
+        kubeconfig,
+    )
+}
+
+fn diff_resource_helm_release(
+    name: &str,
+    namespace: &str,
+    chart_args: Vec<String>,
+    kubeconfig: Option<String>,
+) -> io::Result<bool> {
+    let already_installed = get_helm_release(name, namespace, kubeconfig.clone(), false, None)?
+        .is_some_and(|helm_release| helm_release.status != "uninstalled");
+
+    let live_manifest = if already_installed {
+        crate::utils::run_command_with_captured_stdout(
+            "helm",
+            &["get", "manifest", name, "--namespace", namespace],
+            kubeconfig.clone(),
+            None,
+        )?
+    } else {
+        String::new()
+    };
+
+    // Hooks are not part of the release manifest returned by `helm get manifest`
+    let mut args = vec!["template".to_string(), "--no-hooks".to_string()];
+    if already_installed {
+        args.push("--is-upgrade".to_string());
+    }
+    args.extend(chart_args);
+
+    let desired_manifest = crate::utils::run_command_with_captured_stdout(
+        "helm",
+        args.iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .as_slice(),
+        kubeconfig,
+        None,
+    )?;
+
+    match create_unified_diff(
+        live_manifest.as_str(),
+        desired_manifest.as_str(),
+        format!("live/{}/{}", namespace, name).as_str(),
+        format!("desired/{}/{}", namespace, name).as_str(),
+    ) {
+        None => Ok(false),
+        Some(diff) => {
+            print!("{}", diff);
+            Ok(true)
+        }
+    }
+}
+
+fn create_unified_diff(old: &str, new: &str, old_header: &str, new_header: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+
+    Some(
+        TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_header, new_header)
+            .to_string(),
+    )
+}
+
+#[test]
+fn test_create_unified_diff_returns_none_when_identical() {
+    assert_eq!(None, create_unified_diff("a\n", "a\n", "old", "new"));
+}
+
+#[test]
+fn test_create_unified_diff_returns_diff_with_headers() {
+    assert_eq!(
+        "--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+        create_unified_diff("a\nb\n", "a\nc\n", "old", "new").unwrap()
+    );
+}
@@ -0,0 +1,177 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+#[cfg(test)]
+use std::sync::Mutex;
+#[cfg(test)]
+use std::time::Duration;
+use std::{io, thread};
+
+/// Runs tasks on up to `jobs` threads, starting a task once all the tasks it depends on have
+/// succeeded. When several tasks are ready, they start in the order they were given, so with a
+/// single job tasks run one after another in that order.
+///
+/// After a failure, no new task is started and the first error is returned once the tasks already
+/// running are done. With `keep_going`, tasks not depending on a failed task still start, and the
+/// first error is returned once all of them are done.
+pub(crate) fn run_scheduled<F>(
+    dependencies: &[Vec<usize>],
+    jobs: usize,
+    keep_going: bool,
+    run_task: F,
+) -> io::Result<()>
+where
+    F: Fn(usize) -> io::Result<()> + Sync,
+{
+    let mut started = vec![false; dependencies.len()];
+    let mut succeeded = vec![false; dependencies.len()];
+    let mut running = 0;
+    let mut first_error = None;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| loop {
+        while (keep_going || first_error.is_none()) && running < jobs.max(1) {
+            let next_task = (0..dependencies.len())
+                .find(|&task| !started[task] && dependencies[task].iter().all(|&d| succeeded[d]));
+            let Some(task) = next_task else {
+                break;
+            };
+
+            started[task] = true;
+            running += 1;
+
+            let sender = sender.clone();
+            let run_task = &run_task;
+            scope.spawn(move || {
+                // A panicking task would otherwise never report back and block the scheduler
+                let result = catch_unwind(AssertUnwindSafe(|| run_task(task)))
+                    .unwrap_or_else(|_| Err(io::Error::other(format!("Task {} panicked", task))));
+                sender
+                    .send((task, result))
+                    .expect("Failed to report task result");
+            });
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let (task, result) = receiver.recv().expect("Failed to receive task result");
+        running -= 1;
+        match result {
+            Ok(()) => succeeded[task] = true,
+            Err(err) => {
+                if first_error.is_none() {
+                    first_error = Some(err);
+                }
+            }
+        }
+    });
+
+    if let Some(err) = first_error {
+        return Err(err);
+    }
+    if started.iter().any(|s| !s) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unable to schedule all tasks, dependencies contain a cycle",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the order in which `run_scheduled` starts tasks with a single job, when they all succeed
+pub(crate) fn get_sequential_order(dependencies: &[Vec<usize>]) -> io::Result<Vec<usize>> {
+    let mut order = Vec::new();
+    let mut done = vec![false; dependencies.len()];
+    while let Some(task) = (0..dependencies.len())
+        .find(|&task| !done[task] && dependencies[task].iter().all(|&d| done[d]))
+    {
+        done[task] = true;
+        order.push(task);
+    }
+
+    if order.len() < dependencies.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unable to schedule all tasks, dependencies contain a cycle",
+        ));
+    }
+    Ok(order)
+}
+
+#[test]
+fn test_get_sequential_order_starts_first_ready_task() {
+    assert_eq!(
+        vec![0, 2, 1, 3],
+        get_sequential_order(&[vec![], vec![2], vec![], vec![1]]).unwrap()
+    );
+    assert!(get_sequential_order(&[vec![1], vec![0]]).is_err());
+}
+
+#[test]
+fn test_run_scheduled_with_one_job_runs_tasks_in_given_order() {
+    let order = Mutex::new(Vec::new());
+    run_scheduled(&[vec![], vec![2], vec![], vec![1]], 1, false, |task| {
+        order.lock().unwrap().push(task);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(vec![0, 2, 1, 3], *order.lock().unwrap());
+}
+
+#[test]
+fn test_run_scheduled_runs_up_to_jobs_tasks_at_once() {
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    run_scheduled(&[vec![], vec![], vec![], vec![]], 2, false, |_| {
+        let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+        max_running.fetch_max(now_running, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        running.fetch_sub(1, Ordering::SeqCst);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(2, max_running.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_run_scheduled_stops_starting_tasks_after_failure() {
+    let order = Mutex::new(Vec::new());
+    let err = run_scheduled(&[vec![], vec![0], vec![]], 1, false, |task| {
+        order.lock().unwrap().push(task);
+        if task == 0 {
+            return Err(io::Error::other("Task 0 failed"));
+        }
+        Ok(())
+    })
+    .err()
+    .unwrap();
+    assert_eq!("Task 0 failed", err.to_string());
+    assert_eq!(vec![0], *order.lock().unwrap());
+}
+
+#[test]
+fn test_run_scheduled_reports_panicking_task_as_error() {
+    let err = run_scheduled(&[vec![]], 1, false, |_| panic!("boom"))
+        .err()
+        .unwrap();
+    assert_eq!("Task 0 panicked", err.to_string());
+}
+
+#[test]
+fn test_run_scheduled_with_keep_going_only_skips_tasks_depending_on_failure() {
+    let order = Mutex::new(Vec::new());
+    let err = run_scheduled(&[vec![], vec![0], vec![1], vec![]], 1, true, |task| {
+        order.lock().unwrap().push(task);
+        if task == 0 {
+            return Err(io::Error::other("Task 0 failed"));
+        }
+        Ok(())
+    })
+    .err()
+    .unwrap();
+    assert_eq!("Task 0 failed", err.to_string());
+    assert_eq!(vec![0, 3], *order.lock().unwrap());
+}
@@ -0,0 +1,115 @@
+use crate::utils::TempFile;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+/// Files are encrypted when their name ends in `.enc.yaml`, or when the resource says they all are
+pub(crate) fn is_encrypted_file(path: &str, sops: Option<bool>) -> bool {
+    sops == Some(true)
+        || [".enc.yaml", ".enc.yml", ".enc.json"]
+            .iter()
+            .any(|suffix| path.ends_with(suffix))
+}
+
+/// Decrypts a file with `program`, usually `sops`, into a temporary file only readable by the
+/// current user, deleted when dropped, so that the secrets don't outlive the command using them.
+/// `program` is terminated when it is still running at `deadline`.
+pub(crate) fn decrypt_file(
+    program: &str,
+    path: &str,
+    deadline: Option<Instant>,
+) -> io::Result<TempFile> {
+    let content = crate::utils::run_command_with_captured_stdout(
+        program,
+        &["--decrypt", path],
+        None,
+        deadline,
+    )
+    .map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Could not decrypt {} with sops: {}", path, err),
+        )
+    })?;
+
+    let file_name = Path::new(path)
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    TempFile::create(
+        format!("decrypted-{}", file_name).as_str(),
+        content.as_str(),
+    )
+}
+
+/// Paths to pass to helm or kubectl, with encrypted files replaced by their decrypted copy. The
+/// copies are added to `temp_files`, to outlive the command. With `dry_run`, nothing is decrypted.
+pub(crate) fn get_decrypted_paths(
+    paths: Vec<String>,
+    sops: Option<bool>,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    get_decrypted_paths_with_program("sops", paths, sops, dry_run, deadline, temp_files)
+}
+
+/// Same as [`get_decrypted_paths`], decrypting with `program` instead of `sops`
+fn get_decrypted_paths_with_program(
+    program: &str,
+    paths: Vec<String>,
+    sops: Option<bool>,
+    dry_run: bool,
+    deadline: Option<Instant>,
+    temp_files: &mut Vec<TempFile>,
+) -> io::Result<Vec<String>> {
+    let mut decrypted_paths = Vec::new();
+    for path in paths {
+        if dry_run || !is_encrypted_file(path.as_str(), sops) {
+            decrypted_paths.push(path);
+            continue;
+        }
+        let temp_file = decrypt_file(program, path.as_str(), deadline)?;
+        decrypted_paths.push(temp_file.path().to_string_lossy().to_string());
+        temp_files.push(temp_file);
+    }
+    Ok(decrypted_paths)
+}
+
+#[test]
+fn test_is_encrypted_file_checks_suffix_unless_all_files_are_encrypted() {
+    assert!(is_encrypted_file("secrets.enc.yaml", None));
+    assert!(!is_encrypted_file("values.yaml", None));
+    assert!(is_encrypted_file("values.yaml", Some(true)));
+}
+
+#[test]
+fn test_get_decrypted_paths_decrypts_to_private_temp_file() {
+    // The sops stand-in replaces encrypted values with "decrypted"
+    let mut temp_files = Vec::new();
+    let paths = get_decrypted_paths_with_program(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/sops/sops"),
+        vec![
+            "tests/manifest.yaml".to_string(),
+            "tests/sops/secret.enc.yaml".to_string(),
+        ],
+        None,
+        false,
+        None,
+        &mut temp_files,
+    )
+    .unwrap();
+
+    assert_eq!("tests/manifest.yaml", paths[0]);
+    assert_eq!(temp_files[0].path(), Path::new(paths[1].as_str()));
+    assert!(std::fs::read_to_string(paths[1].as_str())
+        .unwrap()
+        .contains("password: decrypted"));
+    #[cfg(unix)]
+    assert_eq!(
+        0o600,
+        std::os::unix::fs::PermissionsExt::mode(
+            &std::fs::metadata(paths[1].as_str()).unwrap().permissions()
+        ) & 0o777
+    );
+}
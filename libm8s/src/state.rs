@@ -0,0 +1,377 @@
+use crate::file_format::{Hooks, Resource};
+use indexmap::IndexMap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use std::{env, fs, io};
+
+/// Local file with the hash of the inputs of each resource when it last succeeded, so that runs
+/// can skip the resources whose inputs didn't change since. Each target has its own hashes.
+pub struct StateStore {
+    path: PathBuf,
+    target: StateTarget,
+    state_file: Mutex<StateFile>,
+}
+
+/// Cluster and environment the resources are deployed to, so that deploying to one doesn't skip
+/// resources in another
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StateTarget {
+    /// Path of the kubeconfig file, from `--kubeconfig` or `KUBECONFIG`
+    pub kubeconfig: Option<String>,
+    /// Current context of the kubeconfig
+    pub context: Option<String>,
+    /// Environment applied to the configuration
+    pub environment: Option<String>,
+}
+
+impl StateTarget {
+    /// Target of a run with `kubeconfig`, or `KUBECONFIG` when not given, reading its current
+    /// context with kubectl
+    pub fn current(kubeconfig: Option<String>, environment: Option<String>) -> StateTarget {
+        let kubeconfig = kubeconfig.or(env::var("KUBECONFIG").ok());
+        let context = crate::utils::run_command_with_captured_stdout(
+            "kubectl",
+            &["config", "current-context"],
+            kubeconfig.clone(),
+            None,
+        )
+        .map(|context| context.trim().to_string())
+        .inspect_err(|err| {
+            debug!(
+                "No current context in kubeconfig: {}",
+                err.to_string().trim()
+            )
+        })
+        .ok()
+        .filter(|context| !context.is_empty());
+        StateTarget {
+            kubeconfig,
+            context,
+            environment,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateFile {
+    targets: Vec<TargetState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TargetState {
+    #[serde(flatten)]
+    target: StateTarget,
+    /// Input hashes by resource path
+    input_hashes: IndexMap<String, String>,
+}
+
+impl StateFile {
+    fn get_input_hashes(&self, target: &StateTarget) -> Option<&IndexMap<String, String>> {
+        self.targets
+            .iter()
+            .find(|target_state| target_state.target == *target)
+            .map(|target_state| &target_state.input_hashes)
+    }
+
+    fn get_input_hashes_mut(&mut self, target: &StateTarget) -> &mut IndexMap<String, String> {
+        let index = match self
+            .targets
+            .iter()
+            .position(|target_state| target_state.target == *target)
+        {
+            Some(index) => index,
+            None => {
+                self.targets.push(TargetState {
+                    target: target.clone(),
+                    input_hashes: IndexMap::new(),
+                });
+                self.targets.len() - 1
+            }
+        };
+        &mut self.targets[index].input_hashes
+    }
+}
+
+impl StateStore {
+    /// Reads the state file, starting with an empty state when it doesn't exist yet. Hashes are
+    /// read and written for `target` only.
+    pub fn open(path: &Path, target: StateTarget) -> io::Result<StateStore> {
+        let state_file = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unable to parse state file {:?}: {}", path, err),
+                )
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => StateFile::default(),
+            Err(err) => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("Unable to read state file {:?}: {}", path, err),
+                ));
+            }
+        };
+        Ok(StateStore {
+            path: path.to_path_buf(),
+            target,
+            state_file: Mutex::new(state_file),
+        })
+    }
+
+    pub(crate) fn get_input_hash(&self, resource_path: &str) -> Option<String> {
+        self.state_file
+            .lock()
+            .expect("Failed to read state")
+            .get_input_hashes(&self.target)
+            .and_then(|input_hashes| input_hashes.get(resource_path))
+            .cloned()
+    }
+
+    pub(crate) fn set_input_hash(&self, resource_path: &str, input_hash: String) -> io::Result<()> {
+        let mut state_file = self.state_file.lock().expect("Failed to update state");
+        state_file
+            .get_input_hashes_mut(&self.target)
+            .insert(resource_path.to_string(), input_hash);
+        self.save(&state_file)
+    }
+
+    pub(crate) fn remove_input_hash(&self, resource_path: &str) -> io::Result<()> {
+        let mut state_file = self.state_file.lock().expect("Failed to update state");
+        if state_file
+            .get_input_hashes_mut(&self.target)
+            .shift_remove(resource_path)
+            .is_none()
+        {
+            return Ok(());
+        }
+        self.save(&state_file)
+    }
+
+    /// Writes a temporary file renamed over the state file, so that an interrupted run can't leave
+    /// it half written
+    fn save(&self, state_file: &StateFile) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(state_file).map_err(io::Error::other)?;
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, data + "\n")
+            .and_then(|()| fs::rename(&temp_path, &self.path))
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Unable to write state file {:?}: {}", self.path, err),
+                )
+            })
+    }
+}
+
+/// SHA-256 of the definition of a resource and its hooks, and of what it deploys: the content of
+/// its manifests, values files and local chart with its `file://` dependencies, and the output of
+/// `kubectl kustomize`, terminated when still running at `deadline`. Files that shell scripts read
+/// are not part of it.
+pub(crate) fn get_input_hash(
+    resource: &Resource,
+    hooks: Option<&Hooks>,
+    deadline: Option<Instant>,
+) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    update_hash(&mut hasher, &serde_json::to_vec(resource)?);
+    update_hash(&mut hasher, &serde_json::to_vec(&hooks)?);
+
+    match resource {
+        Resource::Manifest { manifest } => {
+            for file in manifest.get_files()? {
+                update_hash_with_path(&mut hasher, Path::new(&file))?;
+            }
+        }
+        Resource::Kustomize { kustomize } => {
+            // The kustomization can pull in bases, components and patches from anywhere
+            let output = crate::utils::run_command_with_captured_stdout(
+                "kubectl",
+                &["kustomize", kustomize.path.as_str()],
+                None,
+                deadline,
+            )
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!(
+                        "Unable to build kustomization {} to hash it: {}",
+                        kustomize.path, err
+                    ),
+                )
+            })?;
+            update_hash(&mut hasher, output.as_bytes());
+        }
+        Resource::HelmRemote { helm_remote } => {
+            for value in helm_remote.values.iter().flatten() {
+                update_hash_with_path(&mut hasher, Path::new(value))?;
+            }
+        }
+        Resource::HelmLocal { helm_local } => {
+            for value in helm_local.values.iter().flatten() {
+                update_hash_with_path(&mut hasher, Path::new(value))?;
+            }
+            update_hash_with_chart(
+                &mut hasher,
+                Path::new(&helm_local.chart_path),
+                &mut Vec::new(),
+            )?;
+        }
+        Resource::Shell { .. }
+        | Resource::Wait { .. }
+        | Resource::Noop { .. }
+        | Resource::Group { .. }
+        | Resource::Include { .. } => {}
+    }
+
+    let input_hash = format!("{:x}", hasher.finalize());
+    debug!("Input hash of {:?} = {}", resource, input_hash);
+    Ok(input_hash)
+}
+
+/// Adds a chart directory, then the charts it depends on with `file://` repositories, which can
+/// be outside of it. `hashed_charts` keeps charts depending on each other from being added forever.
+fn update_hash_with_chart(
+    hasher: &mut Sha256,
+    chart_path: &Path,
+    hashed_charts: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let canonical_chart_path = fs::canonicalize(chart_path).unwrap_or(chart_path.to_path_buf());
+    if hashed_charts.contains(&canonical_chart_path) {
+        return Ok(());
+    }
+    hashed_charts.push(canonical_chart_path);
+    update_hash_with_path(hasher, chart_path)?;
+
+    let chart_file = match fs::read_to_string(chart_path.join("Chart.yaml")) {
+        Ok(chart_file) => chart_file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let chart: serde_yaml::Value = serde_yaml::from_str(&chart_file).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unable to read {:?}: {}",
+                chart_path.join("Chart.yaml"),
+                err
+            ),
+        )
+    })?;
+    let dependency_paths = chart["dependencies"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .filter_map(|dependency| dependency["repository"].as_str())
+        .filter_map(|repository| repository.strip_prefix("file://"))
+        .map(|dependency_path| chart_path.join(dependency_path))
+        .collect::<Vec<_>>();
+    for dependency_path in dependency_paths {
+        update_hash_with_chart(hasher, &dependency_path, hashed_charts)?;
+    }
+    Ok(())
+}
+
+/// Adds data prefixed with its length, so that moving bytes from one part to the next changes
+/// the hash
+fn update_hash(hasher: &mut Sha256, data: &[u8]) {
+    hasher.update((data.len() as u64).to_le_bytes());
+    hasher.update(data);
+}
+
+/// Adds the path and content of a file, or of all the files in a directory sorted by path
+fn update_hash_with_path(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    update_hash(hasher, path.to_string_lossy().as_bytes());
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            update_hash_with_path(hasher, &entry)?;
+        }
+        return Ok(());
+    }
+
+    let data = fs::read(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Unable to read {:?} to hash it: {}", path, err),
+        )
+    })?;
+    update_hash(hasher, &data);
+    Ok(())
+}
+
+#[test]
+fn test_get_input_hash_is_stable_and_changes_with_inputs() {
+    use crate::file_format::Manifest;
+
+    let manifest = Resource::Manifest {
+        manifest: Manifest {
+            path: Some("tests/manifests".to_string()),
+            paths: None,
+            recursive: None,
+            sops: None,
+        },
+    };
+    let input_hash = get_input_hash(&manifest, None, None).unwrap();
+    assert_eq!(64, input_hash.len());
+    assert_eq!(input_hash, get_input_hash(&manifest, None, None).unwrap());
+
+    let other_manifest = Resource::Manifest {
+        manifest: Manifest {
+            path: Some("tests/manifests/crds".to_string()),
+            paths: None,
+            recursive: None,
+            sops: None,
+        },
+    };
+    assert_ne!(
+        input_hash,
+        get_input_hash(&other_manifest, None, None).unwrap()
+    );
+}
+
+#[test]
+fn test_get_input_hash_changes_with_file_dependencies_of_local_charts() {
+    use crate::file_format::{HelmLocal, HelmOptions};
+
+    let dir = env::temp_dir().join(format!("m8s-{}-chart-state", std::process::id()));
+    fs::create_dir_all(dir.join("app")).unwrap();
+    fs::create_dir_all(dir.join("common")).unwrap();
+    fs::write(
+        dir.join("app/Chart.yaml"),
+        "name: app\ndependencies:\n  - name: common\n    repository: file://../common\n",
+    )
+    .unwrap();
+    fs::write(dir.join("common/Chart.yaml"), "name: common\n").unwrap();
+    fs::write(dir.join("common/values.yaml"), "level: info\n").unwrap();
+
+    let helm_local = Resource::HelmLocal {
+        helm_local: HelmLocal {
+            name: "app".to_string(),
+            namespace: "default".to_string(),
+            chart_path: dir.join("app").to_string_lossy().to_string(),
+            values: None,
+            sops: None,
+            values_inline: None,
+            set: None,
+            set_string: None,
+            options: HelmOptions::default(),
+            failed_release_policy: None,
+        },
+    };
+    let input_hash = get_input_hash(&helm_local, None, None);
+    fs::write(dir.join("common/values.yaml"), "level: debug\n").unwrap();
+    let changed_input_hash = get_input_hash(&helm_local, None, None);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_ne!(input_hash.unwrap(), changed_input_hash.unwrap());
+}
@@ -1,8 +1,12 @@
-use log::debug;
-use std::io;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
+use log::{debug, info, warn};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, io, thread};
 
 pub(crate) fn run_command_with_piped_stdio(
     program: &str,
@@ -10,27 +14,282 @@ pub(crate) fn run_command_with_piped_stdio(
     kubeconfig: Option<String>,
     dry_run: bool,
 ) -> io::Result<()> {
+    run_command_with_prefixed_stdio(program, args, kubeconfig, dry_run, None)
+}
+
+/// Same as `run_command_with_piped_stdio`, but prefixes every line of output, so that output of
+/// commands running at the same time can be told apart.
+pub(crate) fn run_command_with_prefixed_stdio(
+    program: &str,
+    args: &[&str],
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    output_prefix: Option<&str>,
+) -> io::Result<()> {
+    run_command_with_deadline(program, args, &[], kubeconfig, dry_run, output_prefix, None)
+}
+
+/// Same as `run_command_with_prefixed_stdio`, with `envs` added to the environment of the
+/// command, but terminates the command and the processes it started when it is still running at
+/// `deadline`, failing with a [`DeadlineExceededError`]
+pub(crate) fn run_command_with_deadline(
+    program: &str,
+    args: &[&str],
+    envs: &[(String, String)],
+    kubeconfig: Option<String>,
+    dry_run: bool,
+    output_prefix: Option<&str>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    if dry_run {
+        info!("Dry run, not running command {} {:?}", program, args);
+        return Ok(());
+    }
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(DeadlineExceededError.into());
+    }
+
     debug!("Running command {} {:?}", program, args);
 
+    let (status, output_stderr) =
+        spawn_with_piped_stdio(program, args, envs, kubeconfig, output_prefix, deadline)?;
+    let Some(status) = status else {
+        warn!(
+            "Terminated command {} {:?}, still running at its deadline",
+            program, args
+        );
+        return Err(DeadlineExceededError.into());
+    };
+    if !status.success() {
+        return Err(io::Error::other(CommandFailedError {
+            exit_code: status.code(),
+            stderr: output_stderr,
+        }));
+    }
+    Ok(())
+}
+
+/// Error of a command that ran and exited with a failure status, shown as the command's stderr
+#[derive(Debug)]
+pub(crate) struct CommandFailedError {
+    exit_code: Option<i32>,
+    stderr: String,
+}
+
+impl fmt::Display for CommandFailedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stderr)
+    }
+}
+
+impl Error for CommandFailedError {}
+
+/// Exit code of the command that failed with `err`, if it ran and wasn't killed by a signal
+pub(crate) fn get_exit_code(err: &io::Error) -> Option<i32> {
+    err.get_ref()
+        .and_then(|inner| inner.downcast_ref::<CommandFailedError>())
+        .and_then(|command_failed_error| command_failed_error.exit_code)
+}
+
+/// Error of a command that was still running at its deadline, and was terminated
+#[derive(Debug)]
+pub(crate) struct DeadlineExceededError;
+
+impl fmt::Display for DeadlineExceededError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline exceeded")
+    }
+}
+
+impl Error for DeadlineExceededError {}
+
+impl From<DeadlineExceededError> for io::Error {
+    fn from(deadline_exceeded_error: DeadlineExceededError) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, deadline_exceeded_error)
+    }
+}
+
+pub(crate) fn is_deadline_exceeded(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<DeadlineExceededError>())
+}
+
+static COMMANDS_OUTPUT_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends the standard output of the commands m8s runs to stderr, so that stdout only has what m8s
+/// writes itself, like JSON events
+pub fn set_commands_output_to_stderr(enabled: bool) {
+    COMMANDS_OUTPUT_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+/// Runs a command with `input` written to its stdin, which is not logged
+pub(crate) fn run_command_with_stdin(
+    program: &str,
+    args: &[&str],
+    input: &str,
+    dry_run: bool,
+) -> io::Result<()> {
     if dry_run {
+        info!("Dry run, not running command {} {:?}", program, args);
         return Ok(());
     }
 
+    debug!("Running command {} {:?}", program, args);
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("Failed to capture stdin")
+        .write_all(input.as_bytes())?;
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(output.stderr.as_slice()).to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Runs a command following the `diff` exit code convention: 0 means no differences, 1 means
+/// differences were found and anything else is an error.
+pub(crate) fn run_diff_command_with_piped_stdio(
+    program: &str,
+    args: &[&str],
+    kubeconfig: Option<String>,
+) -> io::Result<bool> {
+    debug!("Running diff command {} {:?}", program, args);
+
+    let (status, output_stderr) =
+        spawn_with_piped_stdio(program, args, &[], kubeconfig, None, None)?;
+    let status = status.expect("Command without deadline can't be terminated");
+    match status.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(io::Error::other(output_stderr)),
+    }
+}
+
+/// Runs a command and returns its stdout, terminating the command and the processes it started
+/// when it is still running at `deadline`, like [`run_command_with_deadline`]
+pub(crate) fn run_command_with_captured_stdout(
+    program: &str,
+    args: &[&str],
+    kubeconfig: Option<String>,
+    deadline: Option<Instant>,
+) -> io::Result<String> {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(DeadlineExceededError.into());
+    }
+
+    debug!("Running command {} {:?}", program, args);
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if let Some(c) = kubeconfig {
+        command.env("KUBECONFIG", &c);
+    }
+    set_process_group_for_deadline(&mut command, deadline);
+
+    let mut child = command.spawn()?;
+
+    let stdout_handle =
+        read_to_end_in_thread(child.stdout.take().expect("Failed to capture stdout"));
+    let stderr_handle =
+        read_to_end_in_thread(child.stderr.take().expect("Failed to capture stderr"));
+
+    let status = match deadline {
+        None => Some(child.wait()?),
+        Some(deadline) => wait_until_deadline(&mut child, deadline)?,
+    };
+
+    let output_stdout = stdout_handle
+        .join()
+        .expect("Failed to join stdout thread")?;
+    let output_stderr = stderr_handle
+        .join()
+        .expect("Failed to join stderr thread")?;
+
+    let Some(status) = status else {
+        warn!(
+            "Terminated command {} {:?}, still running at its deadline",
+            program, args
+        );
+        return Err(DeadlineExceededError.into());
+    };
+    if !status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(output_stderr.as_slice()).to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(output_stdout.as_slice()).to_string())
+}
+
+/// Reads a pipe of a child process until it is closed, without blocking the other pipes
+fn read_to_end_in_thread(
+    mut reader: impl Read + Send + 'static,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output)?;
+        Ok(output)
+    })
+}
+
+/// In its own process group, the command and the processes it starts can be terminated together.
+/// Only with a deadline, as Ctrl+C no longer reaches them then.
+fn set_process_group_for_deadline(command: &mut Command, deadline: Option<Instant>) {
+    #[cfg(unix)]
+    if deadline.is_some() {
+        std::os::unix::process::CommandExt::process_group(command, 0);
+    }
+    #[cfg(not(unix))]
+    let _ = (command, deadline);
+}
+
+/// Time processes get to exit after being asked to terminate, before they are killed
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs a command printing its output, returning its status and stderr. The status is missing when
+/// the command was terminated at `deadline`.
+fn spawn_with_piped_stdio(
+    program: &str,
+    args: &[&str],
+    envs: &[(String, String)],
+    kubeconfig: Option<String>,
+    output_prefix: Option<&str>,
+    deadline: Option<Instant>,
+) -> io::Result<(Option<ExitStatus>, String)> {
     let mut command = Command::new(program);
     command
         .args(args)
+        .envs(envs.iter().map(|(key, value)| (key, value)))
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
     if let Some(c) = kubeconfig {
-        command.env("KUBECONFIG", c.to_string());
+        command.env("KUBECONFIG", &c);
     }
 
-    let mut child = command.spawn().expect("Failed to execute command");
+    set_process_group_for_deadline(&mut command, deadline);
+
+    let mut child = command.spawn()?;
 
     let stdout = child.stdout.take().expect("Failed to capture stdout");
     let stderr = child.stderr.take().expect("Failed to capture stderr");
 
+    let output_prefix = output_prefix.unwrap_or_default().to_string();
     let output_stdout = Arc::new(Mutex::new(String::new()));
     let output_stderr = Arc::new(Mutex::new(String::new()));
 
@@ -39,10 +298,15 @@ pub(crate) fn run_command_with_piped_stdio(
 
     let stdout_handle = {
         let output_stdout = Arc::clone(&output_stdout);
+        let output_prefix = output_prefix.clone();
         std::thread::spawn(move || {
             for line in stdout_reader.lines() {
                 let line = line.expect("Failed to read line from stdout");
-                println!("{}", line);
+                if COMMANDS_OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+                    eprintln!("{}{}", output_prefix, line);
+                } else {
+                    println!("{}{}", output_prefix, line);
+                }
                 output_stdout.lock().unwrap().push_str(&line);
                 output_stdout.lock().unwrap().push('\n');
             }
@@ -51,24 +315,252 @@ pub(crate) fn run_command_with_piped_stdio(
 
     let stderr_handle = {
         let output_stderr = Arc::clone(&output_stderr);
+        let output_prefix = output_prefix.clone();
         std::thread::spawn(move || {
             for line in stderr_reader.lines() {
                 let line = line.expect("Failed to read line from stderr");
-                eprintln!("{}", line);
+                eprintln!("{}{}", output_prefix, line);
                 output_stderr.lock().unwrap().push_str(&line);
                 output_stderr.lock().unwrap().push('\n');
             }
         })
     };
 
+    let status = match deadline {
+        None => Some(child.wait()?),
+        Some(deadline) => wait_until_deadline(&mut child, deadline)?,
+    };
+
     stdout_handle.join().expect("Failed to join stdout thread");
     stderr_handle.join().expect("Failed to join stderr thread");
 
-    if !child.wait()?.success() {
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            output_stderr.lock().unwrap().to_string(),
-        ));
+    let output_stderr = output_stderr.lock().unwrap().to_string();
+    Ok((status, output_stderr))
+}
+
+/// Waits for the child to exit, terminating it and its process group at `deadline`, then killing
+/// them if they are still running after a grace period. Returns `None` when the child was stopped.
+fn wait_until_deadline(child: &mut Child, deadline: Instant) -> io::Result<Option<ExitStatus>> {
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        thread::sleep(
+            DEADLINE_POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now())),
+        );
+    }
+
+    signal_process_group(child, false)?;
+    let kill_deadline = Instant::now() + TERMINATE_GRACE_PERIOD;
+    while Instant::now() < kill_deadline {
+        if child.try_wait()?.is_some() {
+            break;
+        }
+        thread::sleep(DEADLINE_POLL_INTERVAL);
+    }
+    // Processes of the group may still hold the output pipes, even once the child exited
+    signal_process_group(child, true)?;
+    child.wait()?;
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn signal_process_group(child: &mut Child, kill: bool) -> io::Result<()> {
+    let signal = if kill { libc::SIGKILL } else { libc::SIGTERM };
+    // SAFETY: kill only sends a signal, to the process group the child leads
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), signal) } == -1 {
+        let err = io::Error::last_os_error();
+        // The whole group already exited
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(err);
+        }
     }
     Ok(())
 }
+
+#[cfg(not(unix))]
+fn signal_process_group(child: &mut Child, _kill: bool) -> io::Result<()> {
+    child.kill()
+}
+
+/// File in the temporary directory, deleted when dropped
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl TempFile {
+    /// Creates a new file named after `suffix`, unique within the temporary directory
+    pub(crate) fn create(suffix: &str, content: &str) -> io::Result<TempFile> {
+        let path = std::env::temp_dir().join(format!(
+            "m8s-{}-{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            suffix
+        ));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // Temporary files can hold secrets, other users must not read them
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path)?;
+        let temp_file = TempFile { path };
+        file.write_all(content.as_bytes())?;
+        Ok(temp_file)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            debug!("Could not remove temporary file {:?}: {}", self.path, err);
+        }
+    }
+}
+
+#[test]
+fn test_temp_file_is_deleted_when_dropped() {
+    let temp_file = TempFile::create("test.yaml", "a: 1\n").unwrap();
+    let path = temp_file.path().to_path_buf();
+    assert_eq!("a: 1\n", std::fs::read_to_string(&path).unwrap());
+
+    drop(temp_file);
+    assert!(!path.exists());
+}
+
+/// Parses durations like `90s`, `5m` or `1h30m`, as accepted by kubectl and Helm. A number without
+/// unit is a number of seconds.
+pub fn parse_duration(input: &str) -> io::Result<Duration> {
+    let invalid_duration = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "invalid duration \"{}\", expected a duration like 30s, 5m or 1h30m",
+                input
+            ),
+        )
+    };
+
+    if let Ok(seconds) = input.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    let mut duration = Duration::ZERO;
+    let mut number = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value = number.parse::<u64>().map_err(|_| invalid_duration())?;
+        let seconds = match c {
+            'h' => value.checked_mul(3600),
+            'm' => value.checked_mul(60),
+            's' => Some(value),
+            _ => return Err(invalid_duration()),
+        };
+        duration = seconds
+            .and_then(|seconds| duration.checked_add(Duration::from_secs(seconds)))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("duration \"{}\" is too long", input),
+                )
+            })?;
+        number.clear();
+    }
+    if !number.is_empty() || input.is_empty() {
+        return Err(invalid_duration());
+    }
+    Ok(duration)
+}
+
+#[test]
+fn test_parse_duration_accepts_units_and_seconds() {
+    assert_eq!(Duration::from_secs(90), parse_duration("90").unwrap());
+    assert_eq!(Duration::from_secs(90), parse_duration("1m30s").unwrap());
+    assert_eq!(Duration::from_secs(7200), parse_duration("2h").unwrap());
+}
+
+#[test]
+fn test_parse_duration_fails_with_invalid_duration() {
+    for input in ["", "m", "5d", "1m30"] {
+        assert!(parse_duration(input).is_err(), "{}", input);
+    }
+}
+
+#[test]
+fn test_parse_duration_fails_when_duration_overflows() {
+    for input in ["18446744073709551615h", "18446744073709551615s1s"] {
+        assert_eq!(
+            format!("duration \"{}\" is too long", input),
+            parse_duration(input).err().unwrap().to_string()
+        );
+    }
+}
+
+#[test]
+fn test_run_command_with_captured_stdout_terminates_command_at_deadline() {
+    assert_eq!(
+        "captured\n",
+        run_command_with_captured_stdout(
+            "bash",
+            &["-c", "echo captured"],
+            None,
+            Some(Instant::now() + Duration::from_secs(10)),
+        )
+        .unwrap()
+    );
+
+    let start = Instant::now();
+    let err = run_command_with_captured_stdout(
+        "bash",
+        &["-c", "sleep 10 & sleep 10"],
+        None,
+        Some(Instant::now() + Duration::from_millis(200)),
+    )
+    .err()
+    .unwrap();
+    assert!(is_deadline_exceeded(&err));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_run_command_with_prefixed_stdio_returns_exit_code_of_failed_command() {
+    let err = run_command_with_prefixed_stdio(
+        "bash",
+        &["-c", "echo failed >&2; exit 3"],
+        None,
+        false,
+        None,
+    )
+    .err()
+    .unwrap();
+    assert_eq!("failed\n", err.to_string());
+    assert_eq!(Some(3), get_exit_code(&err));
+}
+
+#[test]
+fn test_run_command_with_deadline_terminates_command_and_its_processes() {
+    let start = Instant::now();
+    let err = run_command_with_deadline(
+        "bash",
+        &["-c", "sleep 10 & sleep 10"],
+        &[],
+        None,
+        false,
+        None,
+        Some(Instant::now() + Duration::from_millis(200)),
+    )
+    .err()
+    .unwrap();
+    assert!(is_deadline_exceeded(&err));
+    assert_eq!(io::ErrorKind::TimedOut, err.kind());
+    // The background sleep holding the output pipes was terminated too
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
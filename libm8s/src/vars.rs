@@ -0,0 +1,382 @@
+use crate::file_format::{Config, Resource, ResourceWithDependencies};
+#[cfg(test)]
+use indexmap::indexmap;
+use indexmap::IndexMap;
+use std::{env, io};
+
+/// Replaces `${NAME}` or `${var.NAME}` with variable NAME and `${env:NAME}` with environment
+/// variable NAME in the string fields of the configuration. `$${` is kept as `${`. Shell scripts
+/// use `${NAME}` for their own variables, so only `${var.NAME}` and `${env:NAME}` are replaced in
+/// them.
+pub(crate) fn interpolate_config(
+    config: &mut Config,
+    vars: &IndexMap<String, String>,
+) -> io::Result<()> {
+    let mut undefined_vars = Vec::new();
+
+    if let Some(ref mut repositories) = config
+        .helm
+        .as_mut()
+        .and_then(|helm| helm.repositories.as_mut())
+    {
+        for repository in repositories.iter_mut() {
+            interpolate_field(&mut repository.name, vars, &mut undefined_vars);
+            interpolate_field(&mut repository.url, vars, &mut undefined_vars);
+        }
+    }
+    if let Some(ref mut registries) = config
+        .helm
+        .as_mut()
+        .and_then(|helm| helm.registries.as_mut())
+    {
+        for registry in registries.iter_mut() {
+            interpolate_field(&mut registry.host, vars, &mut undefined_vars);
+        }
+    }
+    interpolate_resources(&mut config.resources, vars, &mut undefined_vars);
+    for field in config
+        .retry
+        .iter_mut()
+        .flat_map(|retry| [&mut retry.initial_delay, &mut retry.max_delay])
+        .flatten()
+    {
+        interpolate_field(field, vars, &mut undefined_vars);
+    }
+    for environment in config.environments.iter_mut().flat_map(|e| e.values_mut()) {
+        for resource_override in environment.resources.values_mut() {
+            if let Some(ref mut namespace) = resource_override.namespace {
+                interpolate_field(namespace, vars, &mut undefined_vars);
+            }
+            if let Some(ref mut chart_version) = resource_override.chart_version {
+                interpolate_field(chart_version, vars, &mut undefined_vars);
+            }
+            for value in resource_override.values.iter_mut().flatten() {
+                interpolate_field(value, vars, &mut undefined_vars);
+            }
+        }
+    }
+
+    // Deduplicate variables that are used multiple times
+    undefined_vars.sort();
+    undefined_vars.dedup();
+
+    if !undefined_vars.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Configuration is invalid, undefined variables: {}",
+                undefined_vars.join(", ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn interpolate_resources(
+    resources: &mut IndexMap<String, ResourceWithDependencies>,
+    vars: &IndexMap<String, String>,
+    undefined_vars: &mut Vec<String>,
+) {
+    for (
+        _,
+        ResourceWithDependencies {
+            resource,
+            retry,
+            timeout,
+            hooks,
+            ..
+        },
+    ) in resources
+    {
+        for field in retry
+            .iter_mut()
+            .flat_map(|retry| [&mut retry.initial_delay, &mut retry.max_delay])
+            .chain([timeout])
+            .flatten()
+        {
+            interpolate_field(field, vars, undefined_vars);
+        }
+        for script in hooks
+            .iter_mut()
+            .flat_map(|hooks| [&mut hooks.pre, &mut hooks.post, &mut hooks.on_failure])
+            .flatten()
+        {
+            interpolate_script(script, vars, undefined_vars);
+        }
+        match resource {
+            Resource::Shell { ref mut shell } => {
+                interpolate_script(&mut shell.input, vars, undefined_vars);
+                if let Some(ref mut teardown) = shell.teardown {
+                    interpolate_script(teardown, vars, undefined_vars);
+                }
+            }
+            Resource::Manifest { ref mut manifest } => {
+                for path in manifest
+                    .path
+                    .iter_mut()
+                    .chain(manifest.paths.iter_mut().flatten())
+                {
+                    interpolate_field(path, vars, undefined_vars);
+                }
+            }
+            Resource::Kustomize { ref mut kustomize } => {
+                interpolate_field(&mut kustomize.path, vars, undefined_vars);
+            }
+            Resource::Include { ref mut include } => {
+                interpolate_field(&mut include.path, vars, undefined_vars);
+            }
+            Resource::HelmRemote {
+                ref mut helm_remote,
+            } => {
+                interpolate_field(&mut helm_remote.name, vars, undefined_vars);
+                interpolate_field(&mut helm_remote.namespace, vars, undefined_vars);
+                interpolate_field(&mut helm_remote.chart_name, vars, undefined_vars);
+                interpolate_field(&mut helm_remote.chart_version, vars, undefined_vars);
+                for value in helm_remote.values.iter_mut().flatten() {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for value in helm_remote
+                    .values_inline
+                    .iter_mut()
+                    .flat_map(|v| v.values_mut())
+                {
+                    interpolate_value(value, vars, undefined_vars);
+                }
+                for value in helm_remote.set.iter_mut().flat_map(|s| s.values_mut()) {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for value in helm_remote
+                    .set_string
+                    .iter_mut()
+                    .flat_map(|s| s.values_mut())
+                {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for field in [
+                    &mut helm_remote.options.timeout,
+                    &mut helm_remote.options.description,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    interpolate_field(field, vars, undefined_vars);
+                }
+            }
+            Resource::HelmLocal { ref mut helm_local } => {
+                interpolate_field(&mut helm_local.name, vars, undefined_vars);
+                interpolate_field(&mut helm_local.namespace, vars, undefined_vars);
+                interpolate_field(&mut helm_local.chart_path, vars, undefined_vars);
+                for value in helm_local.values.iter_mut().flatten() {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for value in helm_local
+                    .values_inline
+                    .iter_mut()
+                    .flat_map(|v| v.values_mut())
+                {
+                    interpolate_value(value, vars, undefined_vars);
+                }
+                for value in helm_local.set.iter_mut().flat_map(|s| s.values_mut()) {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for value in helm_local
+                    .set_string
+                    .iter_mut()
+                    .flat_map(|s| s.values_mut())
+                {
+                    interpolate_field(value, vars, undefined_vars);
+                }
+                for field in [
+                    &mut helm_local.options.timeout,
+                    &mut helm_local.options.description,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    interpolate_field(field, vars, undefined_vars);
+                }
+            }
+            Resource::Group { ref mut group } => {
+                interpolate_resources(group, vars, undefined_vars);
+            }
+            Resource::Wait { ref mut wait } => {
+                for field in [
+                    &mut wait.rollout,
+                    &mut wait.crd,
+                    &mut wait.namespace,
+                    &mut wait.timeout,
+                    &mut wait.poll_interval,
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    interpolate_field(field, vars, undefined_vars);
+                }
+                if let Some(ref mut condition) = wait.condition {
+                    interpolate_field(&mut condition.object, vars, undefined_vars);
+                    interpolate_field(&mut condition.condition, vars, undefined_vars);
+                    if let Some(ref mut selector) = condition.selector {
+                        interpolate_field(selector, vars, undefined_vars);
+                    }
+                }
+            }
+            Resource::Noop { .. } => {}
+        }
+    }
+}
+
+/// Interpolates the strings within inline values, leaving their keys as they are
+fn interpolate_value(
+    value: &mut serde_json::Value,
+    vars: &IndexMap<String, String>,
+    undefined_vars: &mut Vec<String>,
+) {
+    match value {
+        serde_json::Value::String(string) => interpolate_field(string, vars, undefined_vars),
+        serde_json::Value::Array(array) => {
+            for item in array {
+                interpolate_value(item, vars, undefined_vars);
+            }
+        }
+        serde_json::Value::Object(object) => {
+            for item in object.values_mut() {
+                interpolate_value(item, vars, undefined_vars);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn interpolate_field(
+    field: &mut String,
+    vars: &IndexMap<String, String>,
+    undefined_vars: &mut Vec<String>,
+) {
+    interpolate_field_with(field, |_| true, vars, undefined_vars);
+}
+
+/// Interpolates a shell script, leaving `${NAME}`, `${NAME:-default}` and the like to the shell
+fn interpolate_script(
+    script: &mut String,
+    vars: &IndexMap<String, String>,
+    undefined_vars: &mut Vec<String>,
+) {
+    interpolate_field_with(
+        script,
+        |name| name.starts_with("var.") || name.starts_with("env:"),
+        vars,
+        undefined_vars,
+    );
+}
+
+fn interpolate_field_with(
+    field: &mut String,
+    is_var: impl Fn(&str) -> bool,
+    vars: &IndexMap<String, String>,
+    undefined_vars: &mut Vec<String>,
+) {
+    *field = interpolate(field, is_var, |name| match name.strip_prefix("env:") {
+        Some(env_name) => env::var(env_name).ok(),
+        None => vars.get(name.strip_prefix("var.").unwrap_or(name)).cloned(),
+    })
+    .unwrap_or_else(|names| {
+        undefined_vars.extend(
+            names
+                .iter()
+                .map(|name| name.strip_prefix("var.").unwrap_or(name).to_string()),
+        );
+        field.clone()
+    });
+}
+
+/// Returns the input with variables replaced, or the names of the variables that aren't defined.
+/// `${...}` whose name is not a variable according to `is_var` is kept as is.
+fn interpolate(
+    input: &str,
+    is_var: impl Fn(&str) -> bool,
+    get_var: impl Fn(&str) -> Option<String>,
+) -> Result<String, Vec<String>> {
+    let mut output = String::new();
+    let mut undefined_vars = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find("${") {
+        let name = rest[start + 2..]
+            .find('}')
+            .map(|length| &rest[start + 2..start + 2 + length]);
+        if rest[..start].ends_with('$') && name.is_none_or(&is_var) {
+            output.push_str(&rest[..start - 1]);
+            output.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        output.push_str(&rest[..start]);
+        let Some(name) = name else {
+            // Not a variable without the closing brace, keep it as is
+            output.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+        if !is_var(name) {
+            // Keep scanning within, for variables in defaults like `${NAME:-${var.default}}`
+            output.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        match get_var(name) {
+            Some(value) => output.push_str(value.as_str()),
+            None => undefined_vars.push(name.to_string()),
+        }
+        rest = &rest[start + 2 + name.len() + 1..];
+    }
+    output.push_str(rest);
+
+    if !undefined_vars.is_empty() {
+        return Err(undefined_vars);
+    }
+    Ok(output)
+}
+
+#[test]
+fn test_interpolate_replaces_variables() {
+    let vars = indexmap! {
+        "namespace".to_string() => "production".to_string(),
+        "version".to_string() => "1.2.3".to_string(),
+    };
+    assert_eq!(
+        Ok("production-1.2.3".to_string()),
+        interpolate(
+            "${namespace}-${version}",
+            |_| true,
+            |name| vars.get(name).cloned()
+        )
+    );
+}
+
+#[test]
+fn test_interpolate_keeps_escaped_and_unclosed_variables() {
+    assert_eq!(
+        Ok("echo ${HOME} ${unclosed".to_string()),
+        interpolate("echo $${HOME} ${unclosed", |_| true, |_| None)
+    );
+}
+
+#[test]
+fn test_interpolate_returns_undefined_variables() {
+    assert_eq!(
+        Err(vec!["a".to_string(), "env:B".to_string()]),
+        interpolate("${a} ${env:B}", |_| true, |_| None)
+    );
+}
+
+#[test]
+fn test_interpolate_keeps_other_names() {
+    assert_eq!(
+        Ok("echo ${HOME} $${PWD} ${TAG:-latest} ${NAME:-production}".to_string()),
+        interpolate(
+            "echo ${HOME} $${PWD} ${TAG:-latest} ${NAME:-${var.namespace}}",
+            |name| name.starts_with("var."),
+            |_| Some("production".to_string())
+        )
+    );
+}
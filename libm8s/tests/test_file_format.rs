@@ -1,11 +1,13 @@
 use indexmap::indexmap;
 use libm8s::file_format::Resource::HelmLocal as HelmLocalResource;
 use libm8s::file_format::Resource::HelmRemote as HelmRemoteResource;
+use libm8s::file_format::Resource::Kustomize as KustomizeResource;
 use libm8s::file_format::Resource::Manifest as ManifestResource;
 use libm8s::file_format::{
-    check_dependency_cycles, check_files_exist, check_helm_remote_repositories,
-    check_invalid_resource_keys, check_resource_keys_format, Config, HelmLocal, HelmRemote,
-    HelmRepository, Manifest, Resource, ResourceWithDependencies,
+    check_config, check_dependency_cycles, check_files_exist, check_helm_remote_repositories,
+    check_invalid_resource_keys, check_resource_keys_format, validate_config, Config, HelmLocal,
+    HelmOptions, HelmRemote, HelmRepository, Kustomize, Manifest, Resource,
+    ResourceWithDependencies, ValidationError,
 };
 
 #[test]
@@ -19,7 +21,10 @@ fn test_parse_succeeds_with_only_resources() {
             resource: Resource::Noop {
                 noop: "".to_string()
             },
-            depends_on: None
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None
         }
     )
 }
@@ -95,6 +100,52 @@ fn test_check_dependency_cycles_fails_with_two_dependencies_that_depends_on_each
     );
 }
 
+#[test]
+fn test_check_dependency_cycles_fails_with_a_cycle_across_groups() {
+    let test_file_yaml = include_str!("m8s_cycle_across_groups.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert_eq!(
+        "Configuration is invalid, dependency cycle for \"apps:web\": apps:web -> infra:certManager -> apps:web",
+        check_dependency_cycles(&config.resources)
+            .err()
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_check_invalid_resource_keys_accepts_dependencies_across_groups() {
+    let test_file_yaml = include_str!("m8s_depends_across_groups.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert!(check_invalid_resource_keys(&config.resources).is_ok());
+    assert!(check_dependency_cycles(&config.resources).is_ok());
+}
+
+#[test]
+fn test_check_invalid_resource_keys_accepts_dependencies_on_top_level_resources() {
+    let test_file_yaml = include_str!("m8s_depends_on_top_level.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert!(check_invalid_resource_keys(&config.resources).is_ok());
+    assert!(check_dependency_cycles(&config.resources).is_ok());
+}
+
+#[test]
+fn test_check_invalid_resource_keys_fails_when_a_dependency_path_does_not_exist() {
+    let test_file_yaml = include_str!("m8s_depends_across_groups_not_exists.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert_eq!(
+        "Configuration is invalid, invalid dependencies: infra:doesNotExist",
+        check_invalid_resource_keys(&config.resources)
+            .err()
+            .unwrap()
+            .to_string()
+    );
+}
+
 #[test]
 fn test_check_invalid_resource_keys_fails_when_one_or_more_dependencies_do_not_exist() {
     let test_file_yaml = include_str!("m8s_depends_not_exists.yaml");
@@ -159,9 +210,18 @@ fn test_check_helm_remote_repositories_with_valid_repository_passes() {
                     chart_name: "example/example".to_string(),
                     chart_version: "1.2.3".to_string(),
                     values: None,
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let helm_repositories = Some(vec![HelmRepository {
@@ -171,6 +231,34 @@ fn test_check_helm_remote_repositories_with_valid_repository_passes() {
     check_helm_remote_repositories(&resources, &helm_repositories).unwrap();
 }
 
+#[test]
+fn test_check_helm_remote_repositories_with_oci_chart_passes_without_repositories() {
+    let resources = indexmap! {
+        "foobar".to_string() => ResourceWithDependencies {
+            resource: HelmRemoteResource {
+                helm_remote: HelmRemote {
+                    name: "my-release-name".to_string(),
+                    namespace: "default".to_string(),
+                    chart_name: "oci://registry.example.com/charts/example".to_string(),
+                    chart_version: "1.2.3".to_string(),
+                    values: None,
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        }
+    };
+    check_helm_remote_repositories(&resources, &None).unwrap();
+}
+
 #[test]
 fn test_check_helm_remote_repositories_with_invalid_chart_name_fails() {
     let resources = indexmap! {
@@ -182,9 +270,18 @@ fn test_check_helm_remote_repositories_with_invalid_chart_name_fails() {
                     chart_name: "invalid".to_string(),
                     chart_version: "1.2.3".to_string(),
                     values: None,
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let helm_repositories = Some(vec![HelmRepository {
@@ -210,9 +307,18 @@ fn test_check_helm_remote_repositories_with_invalid_repo_in_chart_name_fails() {
                     chart_name: "invalid/example".to_string(),
                     chart_version: "1.2.3".to_string(),
                     values: None,
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let helm_repositories = Some(vec![]);
@@ -235,9 +341,18 @@ fn test_check_helm_remote_repositories_with_undefined_repository_fails() {
                     chart_name: "example/example".to_string(),
                     chart_version: "1.2.3".to_string(),
                     values: None,
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let helm_repositories = None;
@@ -259,10 +374,16 @@ fn test_check_files_exist_with_valid_manifest_files_passes() {
         "foobar".to_string() => ResourceWithDependencies {
             resource: ManifestResource {
                 manifest: Manifest {
-                    path: "tests/manifest.yaml".to_string()
+                    path: Some("tests/manifest.yaml".to_string()),
+                    paths: None,
+                    recursive: None,
+                    sops: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     check_files_exist(&resources).unwrap()
@@ -274,10 +395,16 @@ fn test_check_files_exist_with_invalid_manifest_files_fails() {
         "foobar".to_string() => ResourceWithDependencies {
             resource: ManifestResource {
                 manifest: Manifest {
-                    path: "invalid-manifest.yaml".to_string()
+                    path: Some("invalid-manifest.yaml".to_string()),
+                    paths: None,
+                    recursive: None,
+                    sops: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let err = check_files_exist(&resources).err().unwrap();
@@ -286,6 +413,110 @@ fn test_check_files_exist_with_invalid_manifest_files_fails() {
         .contains("references file that doesn't exist"))
 }
 
+#[test]
+fn test_check_files_exist_with_manifest_directory_passes() {
+    let resources = indexmap! {
+        "foobar".to_string() => ResourceWithDependencies {
+            resource: ManifestResource {
+                manifest: Manifest {
+                    path: Some("tests/manifests".to_string()),
+                    paths: None,
+                    recursive: None,
+                    sops: None,
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        }
+    };
+    check_files_exist(&resources).unwrap()
+}
+
+#[test]
+fn test_check_files_exist_with_manifest_glob_matching_no_file_fails() {
+    let resources = indexmap! {
+        "foobar".to_string() => ResourceWithDependencies {
+            resource: ManifestResource {
+                manifest: Manifest {
+                    path: None,
+                    paths: Some(vec!["tests/manifests/*.json".to_string()]),
+                    recursive: None,
+                    sops: None,
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        }
+    };
+    let err = check_files_exist(&resources).err().unwrap();
+    assert_eq!(
+        "Invalid resource foobar, references path that doesn't match any manifest file: tests/manifests/*.json",
+        err.to_string()
+    )
+}
+
+#[test]
+fn test_check_files_exist_with_valid_kustomize_directory_passes() {
+    let resources = indexmap! {
+        "foobar".to_string() => ResourceWithDependencies {
+            resource: KustomizeResource {
+                kustomize: Kustomize {
+                    path: "tests/kustomize".to_string()
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        }
+    };
+    check_files_exist(&resources).unwrap();
+}
+
+#[test]
+fn test_check_files_exist_with_invalid_kustomize_directory_fails() {
+    let resources = indexmap! {
+        "foobar".to_string() => ResourceWithDependencies {
+            resource: KustomizeResource {
+                kustomize: Kustomize {
+                    path: "invalid-kustomize".to_string()
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        },
+        "foobaz".to_string() => ResourceWithDependencies {
+            resource: KustomizeResource {
+                kustomize: Kustomize {
+                    path: "tests/chart".to_string()
+                }
+            },
+            depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
+        }
+    };
+    let err = check_files_exist(&resources).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("references directory that doesn't exist"));
+
+    let resources = indexmap! {
+        "foobaz".to_string() => resources["foobaz"].clone(),
+    };
+    let err = check_files_exist(&resources).err().unwrap();
+    assert!(err
+        .to_string()
+        .contains("references directory without kustomization.yaml"));
+}
+
 #[test]
 fn test_check_files_exist_with_valid_helm_remote_files_passes() {
     let resources = indexmap! {
@@ -299,9 +530,18 @@ fn test_check_files_exist_with_valid_helm_remote_files_passes() {
                     values: Some(vec![
                         "tests/chart/values.yaml".to_string()
                     ]),
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     check_files_exist(&resources).unwrap();
@@ -320,9 +560,18 @@ fn test_check_files_exist_with_invalid_helm_remote_files_fails() {
                     values: Some(vec![
                         "invalid-values.yaml".to_string()
                     ]),
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let err = check_files_exist(&resources).err().unwrap();
@@ -342,10 +591,19 @@ fn test_check_files_exist_with_valid_helm_local_files_passes() {
                     values: Some(vec![
                         "tests/chart/values.yaml".to_string()
                     ]),
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                     chart_path: "tests/chart".to_string()
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     check_files_exist(&resources).unwrap();
@@ -362,10 +620,19 @@ fn test_check_files_exist_with_invalid_helm_local_values_fails() {
                     values: Some(vec![
                         "invalid-values.yaml".to_string()
                     ]),
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                     chart_path: "tests/chart".to_string()
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let err = check_files_exist(&resources).err().unwrap();
@@ -385,10 +652,19 @@ fn test_check_files_exist_with_invalid_helm_local_chart_path_fails() {
                     values: Some(vec![
                         "tests/chart/values.yaml".to_string()
                     ]),
+                    sops: None,
+                    values_inline: None,
+                    set: None,
+                    set_string: None,
+                    options: HelmOptions::default(),
+                    failed_release_policy: None,
                     chart_path: "invalid".to_string()
                 }
             },
             depends_on: None,
+            retry: None,
+            hooks: None,
+            timeout: None,
         }
     };
     let err = check_files_exist(&resources).err().unwrap();
@@ -396,3 +672,153 @@ fn test_check_files_exist_with_invalid_helm_local_chart_path_fails() {
         .to_string()
         .contains("references directory that doesn't exist"))
 }
+
+#[test]
+fn test_validate_config_returns_all_problems_with_resource_paths() {
+    let test_file_yaml = include_str!("m8s_multiple_problems.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert_eq!(
+        vec![
+            ValidationError {
+                resource_path: Some("foobarGroup:foobarHelmRemote".to_string()),
+                message: "Configuration is invalid, invalid dependency for \"foobarGroup:foobarHelmRemote\": doesNotExist".to_string(),
+            },
+            ValidationError {
+                resource_path: Some("foobarGroup:foobarNoop".to_string()),
+                message: "Configuration is invalid, dependency cycle for \"foobarGroup:foobarNoop\": foobarNoop -> foobazNoop -> foobarNoop".to_string(),
+            },
+            ValidationError {
+                resource_path: Some("foobarManifest".to_string()),
+                message: "Invalid resource foobarManifest, references file that doesn't exist: does-not-exist.yaml".to_string(),
+            },
+            ValidationError {
+                resource_path: Some("foobarGroup:foobarHelmRemote".to_string()),
+                message: "Invalid resource foobarGroup:foobarHelmRemote, repository with name \"chart\" doesn't exist, no repositories configured".to_string(),
+            },
+        ],
+        validate_config(&config)
+    );
+}
+
+#[test]
+fn test_check_config_lists_all_problems() {
+    let test_file_yaml = include_str!("m8s_multiple_problems.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    let err = check_config(&config).err().unwrap();
+    assert!(err
+        .to_string()
+        .starts_with("Configuration is invalid, found 4 problems:\n  - "));
+}
+
+#[test]
+fn test_check_dependency_cycles_fails_with_cycle_inside_group() {
+    let test_file_yaml = include_str!("m8s_multiple_problems.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    assert_eq!(
+        "Configuration is invalid, dependency cycle for \"foobarGroup:foobarNoop\": foobarNoop -> foobazNoop -> foobarNoop",
+        check_dependency_cycles(&config.resources)
+            .err()
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_validate_config_returns_problems_of_environments() {
+    let test_file_yaml = include_str!("m8s_with_environments.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert_eq!(
+        vec![
+            "Configuration is invalid, environment \"broken\" overrides resource that doesn't exist: doesNotExist",
+            "Configuration is invalid, environment \"broken\" overrides chartVersion of resource \"myGroup:myManifest\", which manifest resources don't have",
+        ],
+        validate_config(&config)
+            .iter()
+            .filter(|validation_error| validation_error.message.contains("environment"))
+            .map(|validation_error| validation_error.message.as_str())
+            .collect::<Vec<&str>>()
+    );
+}
+
+#[test]
+fn test_validate_config_fails_with_invalid_wait() {
+    let config: Config = serde_yaml::from_str(include_str!("m8s_wait_invalid.yaml")).unwrap();
+    assert_eq!(
+        vec![
+            "Invalid resource web, wait needs exactly one of rollout, condition and crd",
+            "Invalid resource web, invalid duration \"2 minutes\", expected a duration like 30s, 5m or 1h30m",
+        ],
+        validate_config(&config)
+            .iter()
+            .map(|validation_error| validation_error.message.as_str())
+            .collect::<Vec<&str>>()
+    );
+}
+
+#[test]
+fn test_validate_config_fails_with_invalid_run_policy() {
+    let config: Config = serde_yaml::from_str(include_str!("m8s_run_policy_invalid.yaml")).unwrap();
+    assert_eq!(
+        vec![
+            "Configuration is invalid, retry needs at least 1 attempt",
+            "Invalid resource web, retry has invalid duration \"a second\", expected a duration like 30s, 5m or 1h30m",
+            "Invalid resource web, timeout has invalid duration \"forever\", expected a duration like 30s, 5m or 1h30m",
+            "Invalid resource jobs, hooks can't be set on groups and includes, set them on their resources",
+        ],
+        validate_config(&config)
+            .iter()
+            .map(|validation_error| validation_error.message.as_str())
+            .collect::<Vec<&str>>()
+    );
+}
+
+#[test]
+fn test_parse_fails_with_unknown_key_in_helm_resource() {
+    let test_file_yaml = include_str!("m8s_unknown_key_in_helm_resource_throws_error.yaml");
+
+    let config = serde_yaml::from_str::<Config>(test_file_yaml);
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_parse_reads_unquoted_numbers_and_booleans_in_helm_set() {
+    let test_file_yaml = include_str!("m8s_helm_set_scalars.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    match &config.resources.get("myHelmRemote").unwrap().resource {
+        HelmRemoteResource { helm_remote } => assert_eq!(
+            Some(indexmap! {
+                "controller.replicaCount".to_string() => "3".to_string(),
+                "controller.metrics.enabled".to_string() => "true".to_string(),
+                "controller.image.tag".to_string() => "v1.11.3".to_string(),
+            }),
+            helm_remote.set
+        ),
+        _ => panic!("Expected Ressource::HelmRemote"),
+    }
+}
+
+#[test]
+fn test_parse_reads_helm_options_of_helm_resources() {
+    let test_file_yaml = include_str!("m8s_helm_options.yaml");
+
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+    match &config.resources.get("myHelmLocal").unwrap().resource {
+        HelmLocalResource { helm_local } => assert_eq!(
+            HelmOptions {
+                create_namespace: Some(true),
+                wait: None,
+                wait_for_jobs: None,
+                timeout: Some("10m".to_string()),
+                atomic: Some(true),
+                description: None,
+            },
+            helm_local.options
+        ),
+        _ => panic!("Expected Ressource::HelmLocal"),
+    }
+}
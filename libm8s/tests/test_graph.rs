@@ -0,0 +1,68 @@
+use libm8s::file_format::Config;
+use libm8s::graph::{create_dot_graph, create_mermaid_graph};
+use libm8s::resources::get_selected_resources;
+
+#[test]
+fn test_create_dot_graph_renders_groups_as_clusters() {
+    let test_file_yaml = include_str!("m8s_graph.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert_eq!(
+        r#"digraph m8s {
+  compound=true;
+  node [shape=box];
+  "certManager" [label="certManager\nmanifest"];
+  subgraph "cluster_apps" {
+    label="apps";
+    "apps" [shape=point, style=invis];
+    "apps:api" [label="api\nnoop"];
+    "apps:web" [label="web\nshell"];
+  }
+  "unrelated" [label="unrelated\nnoop"];
+  "apps:api" -> "apps:web";
+  "certManager" -> "apps" [lhead="cluster_apps"];
+}
+"#,
+        create_dot_graph(&config.resources)
+    );
+}
+
+#[test]
+fn test_create_mermaid_graph_renders_groups_as_subgraphs() {
+    let test_file_yaml = include_str!("m8s_graph.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert_eq!(
+        r#"flowchart LR
+  certManager["certManager (manifest)"]
+  subgraph apps ["apps"]
+    apps_api["api (noop)"]
+    apps_web["web (shell)"]
+  end
+  unrelated["unrelated (noop)"]
+  apps_api --> apps_web
+  certManager --> apps
+"#,
+        create_mermaid_graph(&config.resources)
+    );
+}
+
+#[test]
+fn test_create_mermaid_graph_with_selected_resources_keeps_dependency_closure() {
+    let test_file_yaml = include_str!("m8s_graph.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let resources = get_selected_resources(&config.resources, vec!["apps:web".to_string()], true);
+    assert_eq!(
+        r#"flowchart LR
+  certManager["certManager (manifest)"]
+  subgraph apps ["apps"]
+    apps_api["api (noop)"]
+    apps_web["web (shell)"]
+  end
+  apps_api --> apps_web
+  certManager --> apps
+"#,
+        create_mermaid_graph(&resources)
+    );
+}
@@ -1,5 +1,6 @@
-use libm8s::file_format::HelmRepository;
-use libm8s::helm_repositories::handle_helm_repositories;
+use libm8s::file_format::{HelmRegistry, HelmRepository};
+use libm8s::helm_repositories::{handle_helm_registries, handle_helm_repositories};
+use std::{env, fs};
 
 #[test]
 fn test_handle_helm_repositories_runs_helm_add_and_update_for_each_repository() {
@@ -22,3 +23,54 @@ fn test_handle_helm_repositories_runs_helm_add_and_update_for_each_repository()
         .unwrap()
     );
 }
+
+#[test]
+fn test_handle_helm_registries_logs_in_with_password_on_stdin() {
+    if fs::exists("tests/helm_registry_login_test_output").unwrap_or(false) {
+        fs::remove_file("tests/helm_registry_login_test_output").unwrap();
+    }
+    // The helm stand-in records the logins in place of a registry
+    let path = env::var("PATH").unwrap_or_default();
+    env::set_var(
+        "PATH",
+        format!("{}/tests/registry:{}", env!("CARGO_MANIFEST_DIR"), path),
+    );
+    env::set_var("M8S_TEST_REGISTRY_USERNAME", "robot");
+    env::set_var("M8S_TEST_REGISTRY_PASSWORD", "s3cr3t");
+
+    let dry_run = false;
+    handle_helm_registries(
+        &[HelmRegistry {
+            host: "registry.example.com".to_string(),
+            username_env: "M8S_TEST_REGISTRY_USERNAME".to_string(),
+            password_env: "M8S_TEST_REGISTRY_PASSWORD".to_string(),
+        }],
+        dry_run,
+    )
+    .unwrap();
+
+    let output = fs::read_to_string("tests/helm_registry_login_test_output").unwrap();
+    assert_eq!(
+        "registry login registry.example.com --username robot --password-stdin\ns3cr3t\n",
+        output
+    );
+}
+
+#[test]
+fn test_handle_helm_registries_fails_when_credentials_are_not_set() {
+    let dry_run = true;
+    let err = handle_helm_registries(
+        &[HelmRegistry {
+            host: "registry.example.com".to_string(),
+            username_env: "M8S_TEST_UNSET_USERNAME".to_string(),
+            password_env: "M8S_TEST_UNSET_PASSWORD".to_string(),
+        }],
+        dry_run,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        "Environment variable M8S_TEST_UNSET_USERNAME for helm registry registry.example.com is not set",
+        err.to_string()
+    );
+}
@@ -1,7 +1,8 @@
-use libm8s::file_format::Config;
-use libm8s::file_format::Resource::{Group, HelmLocal, HelmRemote, Manifest};
+use indexmap::{indexmap, IndexMap};
+use libm8s::file_format::Resource::{Group, HelmLocal, HelmRemote, Manifest, Shell};
+use libm8s::file_format::{validate_config, Config, Retry};
 use libm8s::resources::run_resources;
-use libm8s::{parse_deployment_file, FileReader};
+use libm8s::{parse_deployment_file, parse_deployment_file_with_options, FileReader, ParseOptions};
 use std::fs;
 use std::path::Path;
 
@@ -17,11 +18,14 @@ impl FileReader for MockFileReader {
 #[test]
 fn test_parse_deployment_file_with_reader_returns_config() {
     let config =
-        parse_deployment_file(MockFileReader {}, &Path::new("/my/m8s/dir/m8s.yaml")).unwrap();
+        parse_deployment_file(MockFileReader {}, Path::new("/my/m8s/dir/m8s.yaml")).unwrap();
 
     match &config.resources.get("foobarManifest").unwrap().resource {
         Manifest { manifest } => {
-            assert_eq!("/my/m8s/dir/path/to/manifest.yaml", manifest.path.as_str());
+            assert_eq!(
+                Some("/my/m8s/dir/path/to/manifest.yaml".to_string()),
+                manifest.path
+            );
         }
         _ => panic!("Expected Ressource::Manifest"),
     }
@@ -30,7 +34,13 @@ fn test_parse_deployment_file_with_reader_returns_config() {
         HelmLocal { helm_local } => {
             assert_eq!(
                 "/my/m8s/dir/values-local.yaml",
-                helm_local.values.as_ref().unwrap().get(0).unwrap().as_str()
+                helm_local
+                    .values
+                    .as_ref()
+                    .unwrap()
+                    .first()
+                    .unwrap()
+                    .as_str()
             );
         }
         _ => panic!("Expected Ressource::HelmLocal"),
@@ -44,7 +54,7 @@ fn test_parse_deployment_file_with_reader_returns_config() {
                     .values
                     .as_ref()
                     .unwrap()
-                    .get(0)
+                    .first()
                     .unwrap()
                     .as_str()
             );
@@ -55,7 +65,10 @@ fn test_parse_deployment_file_with_reader_returns_config() {
     match &config.resources.get("foobarGroup").unwrap().resource {
         Group { group } => match &group.get("subFoobarManifest").unwrap().resource {
             Manifest { manifest } => {
-                assert_eq!("/my/m8s/dir/sub-manifest.yaml", manifest.path.as_str());
+                assert_eq!(
+                    Some("/my/m8s/dir/sub-manifest.yaml".to_string()),
+                    manifest.path
+                );
             }
             _ => panic!("Expected Ressource::Manifest"),
         },
@@ -63,6 +76,300 @@ fn test_parse_deployment_file_with_reader_returns_config() {
     }
 }
 
+struct StringFileReader(&'static str);
+
+impl FileReader for StringFileReader {
+    fn read_to_string(&self, _: &Path) -> std::io::Result<String> {
+        Ok(self.0.to_string())
+    }
+}
+
+#[test]
+fn test_parse_deployment_file_with_options_interpolates_before_integrating_paths() {
+    std::env::set_var("M8S_TEST_VAR", "from-env");
+    let config = parse_deployment_file_with_options(
+        StringFileReader(include_str!("m8s_with_vars.yaml")),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+        &ParseOptions {
+            vars: indexmap! { "namespace".to_string() => "production".to_string() },
+            environment: None,
+        },
+    )
+    .unwrap();
+
+    match &config.resources.get("myHelmRemote").unwrap().resource {
+        HelmRemote { helm_remote } => {
+            assert_eq!("production", helm_remote.namespace.as_str());
+            assert_eq!("1.0.0", helm_remote.chart_version.as_str());
+            assert_eq!(
+                vec!["/my/m8s/dir/values-production.yaml".to_string()],
+                helm_remote.values.clone().unwrap()
+            );
+            assert_eq!(
+                indexmap! {
+                    "replicaCount".to_string() => serde_json::json!(3),
+                    "ingress".to_string() => serde_json::json!({
+                        "hosts": ["production.example.com"]
+                    }),
+                },
+                helm_remote.values_inline.clone().unwrap()
+            );
+            assert_eq!(
+                indexmap! { "image.tag".to_string() => "1.0.0".to_string() },
+                helm_remote.set.clone().unwrap()
+            );
+        }
+        _ => panic!("Expected Ressource::HelmRemote"),
+    }
+
+    match &config.resources.get("myShell").unwrap().resource {
+        Shell { shell } => assert_eq!("echo from-env 1.0.0 ${HOME}", shell.input.as_str()),
+        _ => panic!("Expected Ressource::Shell"),
+    }
+}
+
+#[test]
+fn test_parse_deployment_file_keeps_shell_variables_in_scripts() {
+    let config = parse_deployment_file(
+        StringFileReader(include_str!("m8s_with_shell_variables.yaml")),
+        Path::new("m8s.yaml"),
+    )
+    .unwrap();
+
+    let my_shell = config.resources.get("myShell").unwrap();
+    match &my_shell.resource {
+        Shell { shell } => {
+            assert_eq!("echo ${HOME} ${TAG:-latest}", shell.input.as_str());
+            assert_eq!(Some("rm -rf \"${TMPDIR}/m8s\""), shell.teardown.as_deref());
+        }
+        _ => panic!("Expected Ressource::Shell"),
+    }
+    assert_eq!(
+        Some("echo ${USER}"),
+        my_shell.hooks.as_ref().unwrap().pre.as_deref()
+    );
+}
+
+#[test]
+fn test_parse_deployment_file_with_options_applies_environment() {
+    let config = parse_deployment_file_with_options(
+        StringFileReader(include_str!("m8s_with_environments.yaml")),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+        &ParseOptions {
+            vars: indexmap! { "namespace".to_string() => "production".to_string() },
+            environment: Some("production".to_string()),
+        },
+    )
+    .unwrap();
+
+    assert_eq!(None, config.environments);
+    match &config.resources.get("myHelmRemote").unwrap().resource {
+        HelmRemote { helm_remote } => {
+            assert_eq!("production", helm_remote.namespace.as_str());
+            assert_eq!("2.0.0", helm_remote.chart_version.as_str());
+            assert_eq!(
+                vec!["/my/m8s/dir/values-production.yaml".to_string()],
+                helm_remote.values.clone().unwrap()
+            );
+        }
+        _ => panic!("Expected Ressource::HelmRemote"),
+    }
+    match &config.resources.get("myGroup").unwrap().resource {
+        Group { group } => {
+            assert_eq!(
+                vec!["myHelmLocal"],
+                group.keys().map(|k| k.as_str()).collect::<Vec<&str>>()
+            );
+            match &group.get("myHelmLocal").unwrap().resource {
+                HelmLocal { helm_local } => assert_eq!(
+                    vec!["/my/m8s/dir/values-local-production.yaml".to_string()],
+                    helm_local.values.clone().unwrap()
+                ),
+                _ => panic!("Expected Ressource::HelmLocal"),
+            }
+            assert_eq!(
+                Some(vec![":myHelmRemote".to_string()]),
+                group.get("myHelmLocal").unwrap().depends_on.clone()
+            );
+        }
+        _ => panic!("Expected Ressource::Group"),
+    } // Dependencies on the disabled myGroup:myManifest are left out
+    assert_eq!(
+        Some(vec!["myHelmRemote".to_string()]),
+        config.resources["myShell"].depends_on.clone()
+    );
+}
+
+#[test]
+fn test_parse_deployment_file_with_options_fails_with_unknown_environment() {
+    assert_eq!(
+        "Configuration is invalid, environment doesn't exist: staging",
+        parse_deployment_file_with_options(
+            StringFileReader(include_str!("m8s_with_environments.yaml")),
+            Path::new("m8s.yaml"),
+            &ParseOptions {
+                vars: IndexMap::new(),
+                environment: Some("staging".to_string()),
+            },
+        )
+        .err()
+        .unwrap()
+        .to_string()
+    );
+}
+
+/// Serves files from memory, with the contents of the included file depending on the test
+struct IncludeFileReader(String);
+
+impl FileReader for IncludeFileReader {
+    fn read_to_string(&self, file_path: &Path) -> std::io::Result<String> {
+        match file_path.to_string_lossy().as_ref() {
+            "/my/m8s/dir/m8s.yaml" => Ok(include_str!("m8s_with_include.yaml").to_string()),
+            "/my/m8s/dir/include/m8s_infra.yaml" => Ok(self.0.clone()),
+            "/my/m8s/dir/include/../m8s_include_cycle.yaml" => {
+                Ok(include_str!("m8s_include_cycle.yaml").to_string())
+            }
+            _ => Err(std::io::Error::from(std::io::ErrorKind::NotFound)),
+        }
+    }
+}
+
+#[test]
+fn test_parse_deployment_file_includes_files_as_groups() {
+    let infra_yaml = include_str!("include/m8s_infra.yaml");
+    let infra_yaml_without_cycle = &infra_yaml[..infra_yaml.find("  issuers:").unwrap()];
+    let config = parse_deployment_file(
+        IncludeFileReader(infra_yaml_without_cycle.to_string()),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec!["ingress-nginx", "jetstack"],
+        config
+            .helm
+            .unwrap()
+            .repositories
+            .unwrap()
+            .iter()
+            .map(|repository| repository.name.as_str())
+            .collect::<Vec<&str>>()
+    );
+    match &config.resources.get("infra").unwrap().resource {
+        Group { group } => match &group.get("certManager").unwrap().resource {
+            HelmRemote { helm_remote } => assert_eq!(
+                vec!["/my/m8s/dir/include/values-cert-manager.yaml".to_string()],
+                helm_remote.values.clone().unwrap()
+            ),
+            _ => panic!("Expected Ressource::HelmRemote"),
+        },
+        _ => panic!("Expected Ressource::Group"),
+    }
+}
+
+#[test]
+fn test_parse_deployment_file_applies_helm_defaults_of_included_files_first() {
+    let infra_yaml = include_str!("include/m8s_infra.yaml");
+    let infra_yaml_with_defaults = infra_yaml[..infra_yaml.find("  issuers:").unwrap()]
+        .replace("helm:\n", "helm:\n  defaults:\n    timeout: 10m\n");
+    let config = parse_deployment_file(
+        IncludeFileReader(infra_yaml_with_defaults),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+    )
+    .unwrap();
+
+    match &config.resources.get("infra").unwrap().resource {
+        Group { group } => match &group.get("certManager").unwrap().resource {
+            HelmRemote { helm_remote } => {
+                assert_eq!(Some(true), helm_remote.options.create_namespace);
+                assert_eq!(Some("10m".to_string()), helm_remote.options.timeout);
+                assert_eq!(None, helm_remote.options.wait);
+            }
+            _ => panic!("Expected Ressource::HelmRemote"),
+        },
+        _ => panic!("Expected Ressource::Group"),
+    }
+}
+
+#[test]
+fn test_parse_deployment_file_reroots_qualified_dependencies_of_included_files() {
+    let config = parse_deployment_file(
+        IncludeFileReader(include_str!("include/m8s_database.yaml").to_string()),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+    )
+    .unwrap();
+
+    match &config.resources.get("infra").unwrap().resource {
+        Group { group } => {
+            match &group.get("api").unwrap().resource {
+                Group { group } => assert_eq!(
+                    Some(vec!["infra:db:migrate".to_string()]),
+                    group.get("server").unwrap().depends_on.clone()
+                ),
+                _ => panic!("Expected Ressource::Group"),
+            }
+            assert_eq!(
+                Some(vec!["api".to_string(), "infra:db:migrate".to_string()]),
+                group.get("seed").unwrap().depends_on.clone()
+            );
+        }
+        _ => panic!("Expected Ressource::Group"),
+    }
+    assert!(validate_config(&config)
+        .iter()
+        .all(|validation_error| !validation_error.message.contains("invalid dependency")));
+}
+
+#[test]
+fn test_parse_deployment_file_applies_retry_of_included_files_to_their_resources() {
+    let infra_yaml = include_str!("include/m8s_infra.yaml");
+    let infra_yaml_with_retry = infra_yaml[..infra_yaml.find("  issuers:").unwrap()]
+        .replace("---\n", "---\nretry:\n  attempts: 3\n");
+    let config = parse_deployment_file(
+        IncludeFileReader(infra_yaml_with_retry),
+        Path::new("/my/m8s/dir/m8s.yaml"),
+    )
+    .unwrap();
+
+    assert_eq!(
+        Some(Retry {
+            attempts: 3,
+            initial_delay: None,
+            max_delay: None,
+        }),
+        config.resources.get("infra").unwrap().retry
+    );
+    assert_eq!(None, config.resources.get("app").unwrap().retry);
+}
+
+#[test]
+fn test_parse_deployment_file_fails_with_include_cycle() {
+    assert_eq!(
+        "Configuration is invalid, include cycle: /my/m8s/dir/m8s.yaml -> /my/m8s/dir/include/m8s_infra.yaml -> /my/m8s/dir/m8s_include_cycle.yaml -> /my/m8s/dir/include/m8s_infra.yaml",
+        parse_deployment_file(
+            IncludeFileReader(include_str!("include/m8s_infra.yaml").to_string()),
+            Path::new("/my/m8s/dir/m8s.yaml"),
+        )
+        .err()
+        .unwrap()
+        .to_string()
+    );
+}
+
+#[test]
+fn test_parse_deployment_file_fails_with_undefined_vars() {
+    assert_eq!(
+        "Configuration is invalid, undefined variables: directory, env:M8S_TEST_UNDEFINED_VAR, message",
+        parse_deployment_file(
+            StringFileReader(include_str!("m8s_with_undefined_vars.yaml")),
+            Path::new("m8s.yaml"),
+        )
+        .err()
+        .unwrap()
+        .to_string()
+    );
+}
+
 #[test]
 fn test_no_dependencies_enables_from_leaf_group_in_resource_args() {
     let test_file_yaml = include_str!("m8s_no_dependencies_leaf_group_in_resource_args.yaml");
@@ -82,6 +389,7 @@ fn test_no_dependencies_enables_from_leaf_group_in_resource_args() {
         false,
         None,
         false,
+        1,
     )
     .unwrap();
 
@@ -109,6 +417,7 @@ fn test_no_dependencies_disables_from_leaf_resource_with_adjacent_resources_in_r
         false,
         None,
         false,
+        1,
     )
     .unwrap();
 
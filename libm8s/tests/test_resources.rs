@@ -1,5 +1,13 @@
 use libm8s::file_format::Config;
-use libm8s::resources::run_resources;
+use libm8s::resources::{
+    diff_resources, down_resources, plan_resources, run_resources, run_resources_with_options,
+    PlannedCommand, PlannedResource, ResourceReport, ResourceStatus, RunEvent, RunOptions,
+};
+use libm8s::state::{StateStore, StateTarget};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[test]
 fn test_run_resources_goes_through_resources_to_run_them() {
@@ -17,8 +25,844 @@ fn test_run_resources_goes_through_resources_to_run_them() {
             vec![],
             skip_dependencies,
             kubeconfig,
-            dry_run
+            dry_run,
+            1
         )
         .unwrap()
     );
 }
+
+#[test]
+fn test_down_resources_goes_through_resources_to_tear_them_down() {
+    let test_file_yaml = include_str!("m8s_only_resources.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let dependencies = true;
+    let kubeconfig = None;
+    let dry_run = true;
+    assert_eq!(
+        (),
+        down_resources(
+            &config.resources,
+            None,
+            config.resources.keys().map(|k| k.to_string()).collect(),
+            dependencies,
+            kubeconfig,
+            dry_run,
+            None
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_down_resources_runs_teardown_in_reverse_dependency_order() {
+    let test_file_yaml = include_str!("m8s_down_reverse_order.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_down_reverse_order_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_down_reverse_order_test_output").unwrap();
+    }
+
+    down_resources(
+        &config.resources,
+        None,
+        vec!["e".to_string()],
+        true,
+        None,
+        false,
+        None,
+    )
+    .unwrap();
+
+    let output = fs::read_to_string("tests/m8s_down_reverse_order_test_output").unwrap();
+    assert_eq!("d\nc\na\n", output);
+}
+
+#[test]
+fn test_diff_resources_reports_no_changes_without_cluster_resources() {
+    let test_file_yaml = include_str!("m8s_diff_without_cluster_resources.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert!(!diff_resources(
+        &config.resources,
+        None,
+        config.resources.keys().map(|k| k.to_string()).collect(),
+        true,
+        None,
+    )
+    .unwrap());
+}
+
+#[test]
+fn test_diff_resources_selects_dependencies_in_other_groups() {
+    let test_file_yaml = include_str!("m8s_diff_depends_across_groups.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert!(!diff_resources(
+        &config.resources,
+        None,
+        vec!["apps:web".to_string()],
+        false,
+        None,
+    )
+    .unwrap());
+    // The manifest of infra:crds matches no file, so diffing it fails
+    assert_eq!(
+        "Manifest path doesn't match any file: tests/manifests/*.json",
+        diff_resources(
+            &config.resources,
+            None,
+            vec!["apps:web".to_string()],
+            true,
+            None,
+        )
+        .err()
+        .unwrap()
+        .to_string()
+    );
+}
+
+#[test]
+fn test_plan_resources_returns_resources_in_order_with_commands() {
+    let test_file_yaml = include_str!("m8s_plan.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert_eq!(
+        vec![
+            PlannedResource {
+                resource_path: "a:b".to_string(),
+                resource_type: "shell".to_string(),
+                commands: vec![PlannedCommand {
+                    program: "bash".to_string(),
+                    args: vec!["-c".to_string(), "echo b".to_string()],
+                }],
+            },
+            PlannedResource {
+                resource_path: "a:c".to_string(),
+                resource_type: "manifest".to_string(),
+                commands: vec![PlannedCommand {
+                    program: "kubectl".to_string(),
+                    args: vec!["apply".to_string(), "-f".to_string(), "c.yaml".to_string()],
+                }],
+            },
+            PlannedResource {
+                resource_path: "d".to_string(),
+                resource_type: "noop".to_string(),
+                commands: vec![],
+            },
+        ],
+        plan_resources(&config.resources, None, vec!["d".to_string()], true, None).unwrap()
+    );
+}
+
+#[test]
+fn test_run_resources_runs_independent_resources_in_parallel_after_dependencies() {
+    let test_file_yaml = include_str!("m8s_run_parallel.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_run_parallel_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_run_parallel_test_output").unwrap();
+    }
+
+    run_resources(
+        &config.resources,
+        None,
+        vec!["d".to_string()],
+        true,
+        None,
+        false,
+        2,
+    )
+    .unwrap();
+
+    // c does not wait for the slower b, but d waits for the whole group
+    let output = fs::read_to_string("tests/m8s_run_parallel_test_output").unwrap();
+    assert_eq!("c\nb\nd\n", output);
+}
+
+#[test]
+fn test_run_resources_runs_dependencies_from_other_groups_first() {
+    let test_file_yaml = include_str!("m8s_depends_across_groups.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_depends_across_groups_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_depends_across_groups_test_output").unwrap();
+    }
+
+    run_resources(
+        &config.resources,
+        None,
+        vec!["apps".to_string()],
+        true,
+        None,
+        false,
+        1,
+    )
+    .unwrap();
+
+    // infra:ingress is not a dependency of apps, so it isn't selected
+    let output = fs::read_to_string("tests/m8s_depends_across_groups_test_output").unwrap();
+    assert_eq!("certManager\nweb\n", output);
+}
+
+#[test]
+fn test_run_resources_runs_dependencies_on_top_level_resources_first() {
+    let test_file_yaml = include_str!("m8s_depends_on_top_level.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_depends_on_top_level_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_depends_on_top_level_test_output").unwrap();
+    }
+
+    run_resources(
+        &config.resources,
+        None,
+        vec!["apps:web".to_string()],
+        true,
+        None,
+        false,
+        1,
+    )
+    .unwrap();
+
+    let output = fs::read_to_string("tests/m8s_depends_on_top_level_test_output").unwrap();
+    assert_eq!("namespace\nweb\n", output);
+}
+
+#[test]
+fn test_plan_resources_orders_dependencies_from_other_groups_first() {
+    let test_file_yaml = include_str!("m8s_depends_across_groups.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    assert_eq!(
+        vec!["infra:ingress", "infra:certManager", "apps:web"],
+        plan_resources(
+            &config.resources,
+            None,
+            config.resources.keys().map(|k| k.to_string()).collect(),
+            true,
+            None
+        )
+        .unwrap()
+        .iter()
+        .map(|planned_resource| planned_resource.resource_path.as_str())
+        .collect::<Vec<&str>>()
+    );
+}
+
+#[test]
+fn test_plan_resources_polls_waits_with_their_poll_interval() {
+    let config: Config = serde_yaml::from_str(include_str!("m8s_with_waits.yaml")).unwrap();
+
+    let resources_args: Vec<String> = config.resources.keys().map(|k| k.to_string()).collect();
+    let dependencies = true;
+    let kubeconfig = None;
+    let planned_resources = plan_resources(
+        &config.resources,
+        None,
+        resources_args.clone(),
+        dependencies,
+        kubeconfig,
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            "kubectl wait crd/certificates.cert-manager.io --for=condition=Established --timeout=5s",
+            "kubectl rollout status deployment/web --namespace web --timeout=10s",
+        ],
+        planned_resources
+            .iter()
+            .flat_map(|planned_resource| planned_resource.commands.iter())
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>()
+    );
+
+    let dry_run = true;
+    run_resources(
+        &config.resources,
+        None,
+        resources_args,
+        dependencies,
+        None,
+        dry_run,
+        1,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_plan_resources_reads_neither_cluster_nor_secrets() {
+    let test_file_yaml = include_str!("m8s_plan_without_cluster.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let dependencies = true;
+    let kubeconfig = None;
+    let planned_resources = plan_resources(
+        &config.resources,
+        None,
+        config.resources.keys().cloned().collect(),
+        dependencies,
+        kubeconfig,
+    )
+    .unwrap();
+
+    assert_eq!(
+        vec![
+            "helm install my-chart repo/chart --version 1.0.0 --namespace default -f '<valuesInline>'"
+                .to_string(),
+            "kubectl apply -f tests/sops/secret.enc.yaml".to_string(),
+        ],
+        planned_resources
+            .iter()
+            .flat_map(|planned_resource| planned_resource.commands.iter())
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>()
+    );
+}
+
+#[test]
+fn test_run_resources_with_options_reports_events_until_failure() {
+    let test_file_yaml = include_str!("m8s_run_events.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let collected_events = events.clone();
+    let run_report = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["c".to_string()],
+        true,
+        &RunOptions {
+            jobs: 1,
+            on_event: Some(Box::new(move |event: &RunEvent| {
+                collected_events.lock().unwrap().push(event.clone());
+            })),
+            ..RunOptions::default()
+        },
+    );
+    assert_eq!(
+        "Running resource b failed: b failed\n",
+        run_report.result.err().unwrap().to_string()
+    );
+    assert_eq!(
+        vec![
+            ("a", ResourceStatus::Ok),
+            ("b", ResourceStatus::Failed),
+            ("c", ResourceStatus::NotRun)
+        ],
+        run_report
+            .resources
+            .iter()
+            .map(|report| (report.resource_path.as_str(), report.status))
+            .collect::<Vec<_>>()
+    );
+
+    // Durations vary from run to run
+    let events: Vec<RunEvent> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .map(|event| match event {
+            RunEvent::CommandExecuted {
+                resource_path,
+                command,
+                exit_code,
+                ..
+            } => RunEvent::CommandExecuted {
+                resource_path,
+                command,
+                exit_code,
+                duration_ms: 0,
+            },
+            RunEvent::ResourceSucceeded { resource_path, .. } => RunEvent::ResourceSucceeded {
+                resource_path,
+                duration_ms: 0,
+            },
+            RunEvent::ResourceFailed {
+                resource_path,
+                exit_code,
+                error,
+                ..
+            } => RunEvent::ResourceFailed {
+                resource_path,
+                exit_code,
+                error,
+                duration_ms: 0,
+            },
+            RunEvent::RunFinished { success, .. } => RunEvent::RunFinished {
+                success,
+                duration_ms: 0,
+            },
+            event => event,
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            RunEvent::PlanResolved {
+                resources: vec!["a".to_string(), "b".to_string(), "c".to_string()]
+            },
+            RunEvent::ResourceStarted {
+                resource_path: "a".to_string(),
+                resource_type: "shell".to_string()
+            },
+            RunEvent::CommandExecuted {
+                resource_path: "a".to_string(),
+                command: "bash -c 'true\n'".to_string(),
+                exit_code: Some(0),
+                duration_ms: 0
+            },
+            RunEvent::ResourceSucceeded {
+                resource_path: "a".to_string(),
+                duration_ms: 0
+            },
+            RunEvent::ResourceStarted {
+                resource_path: "b".to_string(),
+                resource_type: "shell".to_string()
+            },
+            RunEvent::CommandExecuted {
+                resource_path: "b".to_string(),
+                command: "bash -c 'echo b failed >&2 && exit 3\n'".to_string(),
+                exit_code: Some(3),
+                duration_ms: 0
+            },
+            RunEvent::ResourceFailed {
+                resource_path: "b".to_string(),
+                exit_code: Some(3),
+                error: "b failed\n".to_string(),
+                duration_ms: 0
+            },
+            RunEvent::RunFinished {
+                success: false,
+                duration_ms: 0
+            },
+        ],
+        events
+    );
+}
+
+#[test]
+fn test_run_event_serializes_to_json_with_event_name() {
+    assert_eq!(
+        r#"{"event":"resourceFailed","resourcePath":"b","exitCode":3,"error":"failed","durationMs":12}"#,
+        serde_json::to_string(&RunEvent::ResourceFailed {
+            resource_path: "b".to_string(),
+            exit_code: Some(3),
+            error: "failed".to_string(),
+            duration_ms: 12,
+        })
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_run_resources_with_options_reports_dependencies_left_out_as_skipped() {
+    let test_file_yaml = include_str!("m8s_run_events.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let run_report = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["c".to_string()],
+        false,
+        &RunOptions {
+            dry_run: true,
+            jobs: 1,
+            ..RunOptions::default()
+        },
+    );
+    run_report.result.unwrap();
+    assert_eq!(
+        vec![
+            ResourceReport {
+                resource_path: "a".to_string(),
+                resource_type: "shell".to_string(),
+                status: ResourceStatus::Skipped,
+                duration: None,
+            },
+            ResourceReport {
+                resource_path: "b".to_string(),
+                resource_type: "shell".to_string(),
+                status: ResourceStatus::Skipped,
+                duration: None,
+            },
+        ],
+        run_report.resources[..2]
+    );
+    assert_eq!(ResourceStatus::Ok, run_report.resources[2].status);
+    assert!(run_report.resources[2].duration.is_some());
+}
+
+#[test]
+fn test_run_resources_with_options_retries_failed_resources() {
+    let test_file_yaml = include_str!("m8s_with_retry.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    for output in [
+        "tests/m8s_with_retry_test_output",
+        "tests/m8s_with_retry_broken_test_output",
+    ] {
+        if fs::exists(output).unwrap_or(false) {
+            fs::remove_file(output).unwrap();
+        }
+    }
+
+    let run_report = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["flaky".to_string(), "jobs".to_string()],
+        true,
+        &RunOptions {
+            jobs: 1,
+            retry: config.retry.clone(),
+            ..RunOptions::default()
+        },
+    );
+    assert_eq!(
+        "Running resource jobs:broken failed: ",
+        run_report.result.err().unwrap().to_string()
+    );
+
+    // flaky succeeds on its second attempt with the global policy, broken uses up its group's
+    let output = fs::read_to_string("tests/m8s_with_retry_test_output").unwrap();
+    assert_eq!("attempt\nattempt\n", output);
+    let output = fs::read_to_string("tests/m8s_with_retry_broken_test_output").unwrap();
+    assert_eq!("attempt\nattempt\n", output);
+}
+
+#[test]
+fn test_run_resources_with_options_terminates_resources_after_their_timeout() {
+    let test_file_yaml = include_str!("m8s_with_timeout.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let start = Instant::now();
+    let err = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["hung".to_string()],
+        false,
+        &RunOptions {
+            jobs: 1,
+            ..RunOptions::default()
+        },
+    )
+    .result
+    .err()
+    .unwrap();
+    assert_eq!(std::io::ErrorKind::TimedOut, err.kind());
+    assert_eq!(
+        "Running resource hung failed: timed out after 1s",
+        err.to_string()
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_run_resources_with_options_terminates_resources_after_run_timeout() {
+    let test_file_yaml = include_str!("m8s_with_timeout.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let err = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["slow".to_string()],
+        false,
+        &RunOptions {
+            jobs: 1,
+            timeout: Some(Duration::from_millis(500)),
+            ..RunOptions::default()
+        },
+    )
+    .result
+    .err()
+    .unwrap();
+    assert_eq!(
+        "Running resource slow failed: timed out, the run took longer than its timeout of 0.5s",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_run_resources_with_options_stops_retrying_at_run_timeout() {
+    let test_file_yaml = include_str!("m8s_with_timeout.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let start = Instant::now();
+    let err = run_resources_with_options(
+        &config.resources,
+        None,
+        vec!["failing".to_string()],
+        false,
+        &RunOptions {
+            jobs: 1,
+            timeout: Some(Duration::from_millis(500)),
+            ..RunOptions::default()
+        },
+    )
+    .result
+    .err()
+    .unwrap();
+    assert_eq!("Running resource failing failed: ", err.to_string());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_run_resources_runs_hooks_around_resources() {
+    let test_file_yaml = include_str!("m8s_with_hooks.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_with_hooks_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_with_hooks_test_output").unwrap();
+    }
+
+    let err = run_resources(
+        &config.resources,
+        None,
+        vec!["smokeTest".to_string()],
+        true,
+        None,
+        false,
+        1,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        "Running resource smokeTest failed: service unavailable\n",
+        err.to_string()
+    );
+
+    let output = fs::read_to_string("tests/m8s_with_hooks_test_output").unwrap();
+    assert_eq!(
+        "pre app shell\napp\npost\nonFailure service unavailable\n",
+        output
+    );
+}
+
+#[test]
+fn test_plan_resources_lists_pre_and_post_hooks() {
+    let test_file_yaml = include_str!("m8s_with_hooks.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    let planned_resources =
+        plan_resources(&config.resources, None, vec!["app".to_string()], true, None).unwrap();
+    assert_eq!(
+        vec![
+            "bash -c 'echo \"pre $M8S_RESOURCE_PATH $M8S_RESOURCE_TYPE\" >> tests/m8s_with_hooks_test_output'",
+            "bash -c 'echo app >> tests/m8s_with_hooks_test_output\n'",
+            "bash -c 'echo post >> tests/m8s_with_hooks_test_output'",
+        ],
+        planned_resources[0]
+            .commands
+            .iter()
+            .map(|command| command.to_string())
+            .collect::<Vec<String>>()
+    );
+}
+
+#[test]
+fn test_run_resources_with_options_keeps_going_with_resources_not_depending_on_failure() {
+    let test_file_yaml = include_str!("m8s_keep_going.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    if fs::exists("tests/m8s_keep_going_test_output").unwrap_or(false) {
+        fs::remove_file("tests/m8s_keep_going_test_output").unwrap();
+    }
+
+    let run_report = run_resources_with_options(
+        &config.resources,
+        None,
+        config.resources.keys().cloned().collect(),
+        true,
+        &RunOptions {
+            jobs: 1,
+            keep_going: true,
+            ..RunOptions::default()
+        },
+    );
+    assert_eq!(
+        "failed resources: broken, not run because they depend on failed resources: apps:web, migrate\n  \
+         - Running resource broken failed: broken",
+        run_report.result.err().unwrap().to_string()
+    );
+    assert_eq!(
+        vec![
+            ("db", ResourceStatus::Ok),
+            ("broken", ResourceStatus::Failed),
+            ("apps:web", ResourceStatus::NotRun),
+            ("migrate", ResourceStatus::NotRun)
+        ],
+        run_report
+            .resources
+            .iter()
+            .map(|report| (report.resource_path.as_str(), report.status))
+            .collect::<Vec<_>>()
+    );
+
+    let output = fs::read_to_string("tests/m8s_keep_going_test_output").unwrap();
+    assert_eq!("db\n", output);
+}
+
+fn open_state(context: &str) -> StateStore {
+    StateStore::open(
+        Path::new("tests/m8s_with_state_state_test_output"),
+        StateTarget {
+            kubeconfig: None,
+            context: Some(context.to_string()),
+            environment: None,
+        },
+    )
+    .unwrap()
+}
+
+fn run_resources_with_state(
+    config: &Config,
+    state: StateStore,
+    force: bool,
+) -> Vec<(String, ResourceStatus)> {
+    let run_report = run_resources_with_options(
+        &config.resources,
+        None,
+        config.resources.keys().cloned().collect(),
+        true,
+        &RunOptions {
+            jobs: 1,
+            state: Some(state),
+            force,
+            ..RunOptions::default()
+        },
+    );
+    run_report.result.unwrap();
+    run_report
+        .resources
+        .into_iter()
+        .map(|report| (report.resource_path, report.status))
+        .collect()
+}
+
+fn to_statuses(statuses: [(&str, ResourceStatus); 3]) -> Vec<(String, ResourceStatus)> {
+    statuses
+        .into_iter()
+        .map(|(resource_path, status)| (resource_path.to_string(), status))
+        .collect()
+}
+
+#[test]
+fn test_run_resources_with_options_skips_resources_with_unchanged_inputs() {
+    let test_file_yaml = include_str!("m8s_with_state.yaml");
+    let config: Config = serde_yaml::from_str(test_file_yaml).unwrap();
+
+    for file in [
+        "tests/m8s_with_state_test_output",
+        "tests/m8s_with_state_state_test_output",
+    ] {
+        if fs::exists(file).unwrap_or(false) {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    use ResourceStatus::{Ok, Unchanged};
+    assert_eq!(
+        to_statuses([("app", Ok), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&config, open_state("staging"), false)
+    );
+    // Shell resources always run
+    assert_eq!(
+        to_statuses([("app", Unchanged), ("migrate", Unchanged), ("check", Ok)]),
+        run_resources_with_state(&config, open_state("staging"), false)
+    );
+
+    let changed_config: Config =
+        serde_yaml::from_str(&test_file_yaml.replace("echo migrate", "echo migrate again"))
+            .unwrap();
+    assert_eq!(
+        to_statuses([("app", Unchanged), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&changed_config, open_state("staging"), false)
+    );
+    assert_eq!(
+        to_statuses([("app", Ok), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&changed_config, open_state("staging"), true)
+    );
+
+    down_resources(
+        &changed_config.resources,
+        None,
+        vec!["migrate".to_string()],
+        false,
+        None,
+        false,
+        Some(&open_state("staging")),
+    )
+    .unwrap();
+    assert_eq!(
+        to_statuses([("app", Unchanged), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&changed_config, open_state("staging"), false)
+    );
+
+    // Resources depending on a resource that ran run again, whatever their inputs
+    let changed_app_config: Config = serde_yaml::from_str(
+        &test_file_yaml
+            .replace("echo migrate", "echo migrate again")
+            .replace("echo app", "echo app again"),
+    )
+    .unwrap();
+    assert_eq!(
+        to_statuses([("app", Ok), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&changed_app_config, open_state("staging"), false)
+    );
+
+    let output = fs::read_to_string("tests/m8s_with_state_test_output").unwrap();
+    assert_eq!(
+        "app\nmigrate\ncheck\n\
+         check\n\
+         migrate again\ncheck\n\
+         app\nmigrate again\ncheck\n\
+         migrate again\ncheck\n\
+         app again\nmigrate again\ncheck\n",
+        output
+    );
+}
+
+#[test]
+fn test_run_resources_with_options_runs_resources_again_for_another_kubeconfig() {
+    let test_file_yaml = include_str!("m8s_with_state.yaml").replace(
+        "m8s_with_state_test_output",
+        "m8s_with_state_target_test_output",
+    );
+    let config: Config = serde_yaml::from_str(&test_file_yaml).unwrap();
+    let open_state = |kubeconfig: &str| {
+        StateStore::open(
+            Path::new("tests/m8s_with_state_target_state_test_output"),
+            StateTarget {
+                kubeconfig: Some(kubeconfig.to_string()),
+                context: None,
+                environment: None,
+            },
+        )
+        .unwrap()
+    };
+
+    for file in [
+        "tests/m8s_with_state_target_test_output",
+        "tests/m8s_with_state_target_state_test_output",
+    ] {
+        if fs::exists(file).unwrap_or(false) {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    use ResourceStatus::{Ok, Unchanged};
+    assert_eq!(
+        to_statuses([("app", Ok), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&config, open_state("staging.yaml"), false)
+    );
+    assert_eq!(
+        to_statuses([("app", Ok), ("migrate", Ok), ("check", Ok)]),
+        run_resources_with_state(&config, open_state("production.yaml"), false)
+    );
+    // Each kubeconfig keeps its own state
+    assert_eq!(
+        to_statuses([("app", Unchanged), ("migrate", Unchanged), ("check", Ok)]),
+        run_resources_with_state(&config, open_state("staging.yaml"), false)
+    );
+}
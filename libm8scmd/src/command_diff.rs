@@ -0,0 +1,144 @@
+use crate::utils::{CommandRunner, NativeFileReader};
+use crate::{OptionDependencies, OptionHelmRepositories, OptionVars};
+use std::error::Error;
+use std::path::Path;
+use std::{fmt, io};
+
+/// Returned by `m8s diff` when at least one resource would change, so that callers can tell
+/// changes apart from failures.
+#[derive(Debug)]
+pub struct ChangesDetected;
+
+impl fmt::Display for ChangesDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Running resources would change the cluster")
+    }
+}
+
+impl Error for ChangesDetected {}
+
+pub struct CommandDiff {
+    pub resources_args: Vec<String>,
+    pub file: Option<String>,
+    pub kubeconfig: Option<String>,
+    pub helm_repositories: OptionHelmRepositories,
+    pub dependencies: OptionDependencies,
+    pub vars: OptionVars,
+}
+
+impl CommandRunner for CommandDiff {
+    fn run(&self) -> io::Result<()> {
+        if (self.dependencies.dependencies || self.dependencies.no_dependencies)
+            && self.resources_args.is_empty()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "option --dependencies/--no-dependencies only works when you pass argument RESOURCES too"
+                    .to_string(),
+            ));
+        }
+
+        let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
+        let config = libm8s::parse_deployment_file_with_options(
+            NativeFileReader {},
+            Path::new(deployment_file_path.as_str()),
+            &self.vars.get_parse_options()?,
+        )?;
+
+        libm8s::file_format::check_config(&config)?;
+
+        if self.helm_repositories.get_value() {
+            libm8s::helm_repositories::handle_helm_repositories(
+                config
+                    .helm
+                    .as_ref()
+                    .and_then(|helm| helm.repositories.clone())
+                    .unwrap_or(Vec::new())
+                    .as_slice(),
+                false,
+            )
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Adding helm repositories failed: {}", err),
+                )
+            })?;
+            libm8s::helm_repositories::handle_helm_registries(
+                config
+                    .helm
+                    .as_ref()
+                    .and_then(|helm| helm.registries.clone())
+                    .unwrap_or(Vec::new())
+                    .as_slice(),
+                false,
+            )
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Logging into helm registries failed: {}", err),
+                )
+            })?;
+        }
+
+        let resource_args = if !self.resources_args.is_empty() {
+            self.resources_args.clone()
+        } else {
+            config.resources.keys().map(|k| k.to_string()).collect()
+        };
+        let changed = libm8s::resources::diff_resources(
+            &config.resources,
+            None,
+            resource_args,
+            self.dependencies.get_value(),
+            self.kubeconfig.clone(),
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("Diffing resources failed: {}", err)))?;
+
+        if changed {
+            return Err(io::Error::other(ChangesDetected));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_diff::{ChangesDetected, CommandDiff};
+    use crate::utils::{with_directory, CommandRunner};
+    use crate::{
+        get_exit_code, OptionDependencies, OptionHelmRepositories, OptionVars,
+        EXIT_CODE_CHANGES_DETECTED,
+    };
+    use std::io;
+
+    #[test]
+    fn test_command_diff_reads_from_m8s_yaml_by_default() {
+        with_directory(Some("tests".to_string()), || {
+            let cmd = CommandDiff {
+                resources_args: vec![],
+                file: None,
+                vars: OptionVars::default(),
+                kubeconfig: None,
+                helm_repositories: OptionHelmRepositories {
+                    helm_repositories: false,
+                    no_helm_repositories: true,
+                },
+                dependencies: OptionDependencies {
+                    dependencies: false,
+                    no_dependencies: false,
+                },
+            };
+            cmd.run()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_changes_detected_maps_to_dedicated_exit_code() {
+        assert_eq!(
+            EXIT_CODE_CHANGES_DETECTED,
+            get_exit_code(&io::Error::other(ChangesDetected))
+        );
+    }
+}
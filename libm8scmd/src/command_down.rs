@@ -0,0 +1,129 @@
+use crate::utils::{get_state_file_path, CommandRunner, NativeFileReader};
+use crate::{OptionDependencies, OptionVars};
+use libm8s::state::{StateStore, StateTarget};
+use std::io;
+use std::path::Path;
+
+pub struct CommandDown {
+    pub resources_args: Vec<String>,
+    pub file: Option<String>,
+    pub kubeconfig: Option<String>,
+    pub dependencies: OptionDependencies,
+    pub vars: OptionVars,
+    pub dry_run: bool,
+    pub state_file: Option<String>,
+}
+
+impl CommandRunner for CommandDown {
+    fn run(&self) -> io::Result<()> {
+        if (self.dependencies.dependencies || self.dependencies.no_dependencies)
+            && self.resources_args.is_empty()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "option --dependencies/--no-dependencies only works when you pass argument RESOURCES too"
+                    .to_string(),
+            ));
+        }
+
+        let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
+        let config = libm8s::parse_deployment_file_with_options(
+            NativeFileReader {},
+            Path::new(deployment_file_path.as_str()),
+            &self.vars.get_parse_options()?,
+        )?;
+
+        libm8s::file_format::check_config_without_files(&config)?;
+        let state = StateStore::open(
+            &get_state_file_path(self.state_file.as_ref(), &deployment_file_path),
+            StateTarget::current(self.kubeconfig.clone(), self.vars.environment.clone()),
+        )?;
+
+        let resource_args = if !self.resources_args.is_empty() {
+            self.resources_args.clone()
+        } else {
+            config.resources.keys().map(|k| k.to_string()).collect()
+        };
+        libm8s::resources::down_resources(
+            &config.resources,
+            None,
+            resource_args,
+            self.dependencies.get_value(),
+            self.kubeconfig.clone(),
+            self.dry_run,
+            Some(&state),
+        )
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("Tearing down resources failed: {}", err),
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_down::CommandDown;
+    use crate::utils::{with_directory, CommandRunner};
+    use crate::{OptionDependencies, OptionVars};
+
+    #[test]
+    fn test_command_down_reads_from_m8s_yaml_by_default() {
+        with_directory(Some("tests".to_string()), || {
+            let cmd = CommandDown {
+                resources_args: vec![],
+                file: None,
+                vars: OptionVars::default(),
+                kubeconfig: None,
+                dependencies: OptionDependencies {
+                    dependencies: false,
+                    no_dependencies: false,
+                },
+                dry_run: true,
+                state_file: None,
+            };
+            cmd.run()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_command_down_requires_resources_with_dependencies_option() {
+        let cmd = CommandDown {
+            resources_args: vec![],
+            file: Some("tests/m8s.yaml".to_string()),
+            vars: OptionVars::default(),
+            kubeconfig: None,
+            dependencies: OptionDependencies {
+                dependencies: false,
+                no_dependencies: true,
+            },
+            dry_run: true,
+            state_file: None,
+        };
+        let err = with_directory(None, || cmd.run()).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("only works when you pass argument RESOURCES"));
+    }
+
+    #[test]
+    fn test_command_down_ignores_missing_values_files() {
+        let cmd = CommandDown {
+            resources_args: vec![],
+            file: Some("tests/m8s_with_missing_values_file.yaml".to_string()),
+            vars: OptionVars::default(),
+            kubeconfig: None,
+            dependencies: OptionDependencies {
+                dependencies: false,
+                no_dependencies: false,
+            },
+            dry_run: true,
+            state_file: None,
+        };
+        with_directory(None, || cmd.run()).unwrap();
+    }
+}
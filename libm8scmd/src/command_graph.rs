@@ -0,0 +1,92 @@
+use crate::utils::{CommandRunner, NativeFileReader};
+use crate::{GraphFormat, OptionDependencies, OptionVars};
+use std::io;
+use std::path::Path;
+
+pub struct CommandGraph {
+    pub resources_args: Vec<String>,
+    pub file: Option<String>,
+    pub dependencies: OptionDependencies,
+    pub vars: OptionVars,
+    pub format: GraphFormat,
+}
+
+impl CommandRunner for CommandGraph {
+    fn run(&self) -> io::Result<()> {
+        if (self.dependencies.dependencies || self.dependencies.no_dependencies)
+            && self.resources_args.is_empty()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "option --dependencies/--no-dependencies only works when you pass argument RESOURCES too"
+                    .to_string(),
+            ));
+        }
+
+        let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
+        let config = libm8s::parse_deployment_file_with_options(
+            NativeFileReader {},
+            Path::new(deployment_file_path.as_str()),
+            &self.vars.get_parse_options()?,
+        )?;
+
+        libm8s::file_format::check_config(&config)?;
+
+        let resources = if !self.resources_args.is_empty() {
+            libm8s::resources::get_selected_resources(
+                &config.resources,
+                self.resources_args.clone(),
+                self.dependencies.get_value(),
+            )
+        } else {
+            config.resources
+        };
+
+        match self.format {
+            GraphFormat::Dot => print!("{}", libm8s::graph::create_dot_graph(&resources)),
+            GraphFormat::Mermaid => print!("{}", libm8s::graph::create_mermaid_graph(&resources)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_graph::CommandGraph;
+    use crate::utils::{with_directory, CommandRunner};
+    use crate::{GraphFormat, OptionDependencies, OptionVars};
+
+    #[test]
+    fn test_command_graph_reads_from_m8s_yaml_by_default() {
+        with_directory(Some("tests".to_string()), || {
+            let cmd = CommandGraph {
+                resources_args: vec![],
+                file: None,
+                vars: OptionVars::default(),
+                dependencies: OptionDependencies {
+                    dependencies: false,
+                    no_dependencies: false,
+                },
+                format: GraphFormat::Dot,
+            };
+            cmd.run()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_command_graph_restricts_to_resources() {
+        let cmd = CommandGraph {
+            resources_args: vec!["myNoop".to_string()],
+            file: Some("tests/m8s.yaml".to_string()),
+            vars: OptionVars::default(),
+            dependencies: OptionDependencies {
+                dependencies: false,
+                no_dependencies: true,
+            },
+            format: GraphFormat::Mermaid,
+        };
+        with_directory(None, || cmd.run()).unwrap();
+    }
+}
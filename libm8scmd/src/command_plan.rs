@@ -0,0 +1,119 @@
+use crate::utils::{CommandRunner, NativeFileReader};
+use crate::{OptionDependencies, OptionVars, OutputFormat};
+use std::io;
+use std::path::Path;
+
+pub struct CommandPlan {
+    pub resources_args: Vec<String>,
+    pub file: Option<String>,
+    pub kubeconfig: Option<String>,
+    pub dependencies: OptionDependencies,
+    pub vars: OptionVars,
+    pub output: OutputFormat,
+}
+
+impl CommandRunner for CommandPlan {
+    fn run(&self) -> io::Result<()> {
+        if (self.dependencies.dependencies || self.dependencies.no_dependencies)
+            && self.resources_args.is_empty()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "option --dependencies/--no-dependencies only works when you pass argument RESOURCES too"
+                    .to_string(),
+            ));
+        }
+
+        let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
+        let config = libm8s::parse_deployment_file_with_options(
+            NativeFileReader {},
+            Path::new(deployment_file_path.as_str()),
+            &self.vars.get_parse_options()?,
+        )?;
+
+        libm8s::file_format::check_config(&config)?;
+
+        let resource_args = if !self.resources_args.is_empty() {
+            self.resources_args.clone()
+        } else {
+            config.resources.keys().map(|k| k.to_string()).collect()
+        };
+        let planned_resources = libm8s::resources::plan_resources(
+            &config.resources,
+            None,
+            resource_args,
+            self.dependencies.get_value(),
+            self.kubeconfig.clone(),
+        )
+        .map_err(|err| io::Error::new(err.kind(), format!("Planning resources failed: {}", err)))?;
+
+        match self.output {
+            OutputFormat::Text => {
+                for (index, planned_resource) in planned_resources.iter().enumerate() {
+                    println!(
+                        "{}. {} ({})",
+                        index + 1,
+                        planned_resource.resource_path,
+                        planned_resource.resource_type
+                    );
+                    for command in planned_resource.commands.iter() {
+                        println!("   {}", command);
+                    }
+                }
+            }
+            OutputFormat::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "kubeconfig": self.kubeconfig,
+                        "resources": planned_resources,
+                    }))?
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_plan::CommandPlan;
+    use crate::utils::{with_directory, CommandRunner};
+    use crate::{OptionDependencies, OptionVars, OutputFormat};
+
+    #[test]
+    fn test_command_plan_reads_from_m8s_yaml_by_default() {
+        with_directory(Some("tests".to_string()), || {
+            let cmd = CommandPlan {
+                resources_args: vec![],
+                file: None,
+                vars: OptionVars::default(),
+                kubeconfig: None,
+                dependencies: OptionDependencies {
+                    dependencies: false,
+                    no_dependencies: false,
+                },
+                output: OutputFormat::Text,
+            };
+            cmd.run()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_command_plan_outputs_json() {
+        let cmd = CommandPlan {
+            resources_args: vec!["myNoop".to_string()],
+            file: Some("tests/m8s.yaml".to_string()),
+            vars: OptionVars::default(),
+            kubeconfig: Some("kubeconfig.yaml".to_string()),
+            dependencies: OptionDependencies {
+                dependencies: true,
+                no_dependencies: false,
+            },
+            output: OutputFormat::Json,
+        };
+        with_directory(None, || cmd.run()).unwrap();
+    }
+}
@@ -1,8 +1,11 @@
-use crate::utils::CommandRunner;
-use crate::{OptionDependencies, OptionHelmRepositories, OptionResources};
-use libm8s::FileReader;
+use crate::utils::{get_state_file_path, CommandRunner, NativeFileReader};
+use crate::{
+    OptionDependencies, OptionHelmRepositories, OptionResources, OptionVars, OutputFormat,
+};
+use libm8s::resources::{EventHandler, ResourceReport, RunEvent, RunOptions};
+use libm8s::state::{StateStore, StateTarget};
+use std::io;
 use std::path::Path;
-use std::{fs, io};
 
 pub struct CommandUp {
     pub resources_args: Vec<String>,
@@ -12,13 +15,30 @@ pub struct CommandUp {
     pub resources: OptionResources,
     pub dependencies: OptionDependencies,
     pub dry_run: bool,
+    pub jobs: u32,
+    pub vars: OptionVars,
+    pub output: OutputFormat,
+    pub timeout: Option<String>,
+    pub keep_going: bool,
+    pub force: bool,
+    pub state_file: Option<String>,
 }
 
 impl CommandRunner for CommandUp {
     fn run(&self) -> io::Result<()> {
+        // Keep stdout for the events, the output of helm and kubectl goes to stderr with the logs
+        libm8s::utils::set_commands_output_to_stderr(self.output == OutputFormat::Json);
+        let result = self.run_up();
+        libm8s::utils::set_commands_output_to_stderr(false);
+        result
+    }
+}
+
+impl CommandUp {
+    fn run_up(&self) -> io::Result<()> {
         if (self.dependencies.dependencies || self.dependencies.no_dependencies)
             && self.resources.get_value()
-            && self.resources_args.len() == 0
+            && self.resources_args.is_empty()
         {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -26,7 +46,7 @@ impl CommandRunner for CommandUp {
                     .to_string(),
             ));
         }
-        if self.resources.no_resources && self.resources_args.len() > 0 {
+        if self.resources.no_resources && !self.resources_args.is_empty() {
             return Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 format!(
@@ -36,23 +56,27 @@ impl CommandRunner for CommandUp {
             ));
         }
 
+        let timeout = match self.timeout {
+            None => None,
+            Some(ref timeout) => Some(libm8s::utils::parse_duration(timeout).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "option --timeout expects a duration like 30s, 5m or 1h30m, got {}",
+                        timeout
+                    ),
+                )
+            })?),
+        };
+
         let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
-        let config = libm8s::parse_deployment_file(
+        let config = libm8s::parse_deployment_file_with_options(
             NativeFileReader {},
-            &Path::new(deployment_file_path.as_str()),
+            Path::new(deployment_file_path.as_str()),
+            &self.vars.get_parse_options()?,
         )?;
 
-        libm8s::file_format::check_resource_keys_format(&config.resources)?;
-        libm8s::file_format::check_invalid_resource_keys(&config.resources)?;
-        libm8s::file_format::check_dependency_cycles(&config.resources)?;
-        libm8s::file_format::check_files_exist(&config.resources)?;
-        libm8s::file_format::check_helm_remote_repositories(
-            &config.resources,
-            &config
-                .helm
-                .as_ref()
-                .and_then(|helm| helm.repositories.clone()),
-        )?;
+        libm8s::file_format::check_config(&config)?;
 
         if self.helm_repositories.get_value() {
             libm8s::helm_repositories::handle_helm_repositories(
@@ -70,23 +94,65 @@ impl CommandRunner for CommandUp {
                     format!("Adding helm repositories failed: {}", err),
                 )
             })?;
+            libm8s::helm_repositories::handle_helm_registries(
+                config
+                    .helm
+                    .as_ref()
+                    .and_then(|helm| helm.registries.clone())
+                    .unwrap_or(Vec::new())
+                    .as_slice(),
+                self.dry_run,
+            )
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Logging into helm registries failed: {}", err),
+                )
+            })?;
         }
 
         if self.resources.get_value() {
-            let resource_args = if self.resources_args.len() > 0 {
+            let resource_args = if !self.resources_args.is_empty() {
                 self.resources_args.clone()
             } else {
                 config.resources.keys().map(|k| k.to_string()).collect()
             };
-            libm8s::resources::run_resources(
+            let state = StateStore::open(
+                &get_state_file_path(self.state_file.as_ref(), &deployment_file_path),
+                StateTarget::current(self.kubeconfig.clone(), self.vars.environment.clone()),
+            )?;
+            let on_event: Option<EventHandler> = match self.output {
+                OutputFormat::Text => None,
+                OutputFormat::Json => Some(Box::new(|event: &RunEvent| {
+                    println!(
+                        "{}",
+                        serde_json::to_string(event).expect("Failed to serialize run event")
+                    );
+                })),
+            };
+            let run_report = libm8s::resources::run_resources_with_options(
                 &config.resources,
                 None,
                 resource_args,
                 self.dependencies.get_value(),
-                self.kubeconfig.clone(),
-                self.dry_run,
-            )
-            .map_err(|err| {
+                &RunOptions {
+                    kubeconfig: self.kubeconfig.clone(),
+                    dry_run: self.dry_run,
+                    jobs: self.jobs as usize,
+                    retry: config.retry.clone(),
+                    timeout,
+                    keep_going: self.keep_going,
+                    state: Some(state),
+                    force: self.force,
+                    on_event,
+                },
+            );
+            let summary = format_summary(&run_report.resources);
+            match self.output {
+                OutputFormat::Text => print!("{}", summary),
+                OutputFormat::Json => eprint!("{}", summary),
+            }
+            run_report.result.map_err(|err| {
                 io::Error::new(err.kind(), format!("Running resources failed: {}", err))
             })?;
         }
@@ -95,19 +161,54 @@ impl CommandRunner for CommandUp {
     }
 }
 
-struct NativeFileReader;
+/// Table of the resources of a run with their status and how long they took
+fn format_summary(resource_reports: &[ResourceReport]) -> String {
+    let rows: Vec<[String; 4]> = resource_reports
+        .iter()
+        .map(|resource_report| {
+            [
+                resource_report.resource_path.clone(),
+                resource_report.resource_type.clone(),
+                resource_report.status.to_string(),
+                match resource_report.duration {
+                    Some(duration) => format!("{:.1}s", duration.as_secs_f64()),
+                    None => "-".to_string(),
+                },
+            ]
+        })
+        .collect();
+    let header = ["RESOURCE", "TYPE", "STATUS", "DURATION"].map(|title| title.to_string());
+
+    let mut widths = [0; 4];
+    for row in rows.iter().chain([&header]) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
 
-impl FileReader for NativeFileReader {
-    fn read_to_string(&self, file_path: &Path) -> io::Result<String> {
-        fs::read_to_string(file_path)
+    let mut summary = String::new();
+    for row in [&header].into_iter().chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        summary.push_str(line.trim_end());
+        summary.push('\n');
     }
+    summary
 }
 
 #[cfg(test)]
 mod test {
-    use crate::command_up::CommandUp;
+    use crate::command_up::{format_summary, CommandUp};
     use crate::utils::{with_directory, CommandRunner};
-    use crate::{OptionDependencies, OptionHelmRepositories, OptionResources};
+    use crate::{
+        OptionDependencies, OptionHelmRepositories, OptionResources, OptionVars, OutputFormat,
+    };
+    use libm8s::resources::{ResourceReport, ResourceStatus};
+    use std::time::Duration;
 
     #[test]
     fn test_command_up_reads_from_m8s_yaml_by_default() {
@@ -129,6 +230,13 @@ mod test {
                     no_dependencies: true,
                 },
                 dry_run: true,
+                jobs: 1,
+                vars: OptionVars::default(),
+                output: OutputFormat::Text,
+                timeout: None,
+                keep_going: false,
+                force: false,
+                state_file: None,
             };
             cmd.run()
         })
@@ -154,7 +262,103 @@ mod test {
                 no_dependencies: true,
             },
             dry_run: true,
+            jobs: 1,
+            vars: OptionVars::default(),
+            output: OutputFormat::Text,
+            timeout: None,
+            keep_going: false,
+            force: false,
+            state_file: None,
+        };
+        with_directory(None, || cmd.run()).unwrap();
+    }
+
+    #[test]
+    fn test_command_up_outputs_json_events() {
+        let cmd = CommandUp {
+            resources_args: vec![],
+            file: Some("tests/m8s.yaml".to_string()),
+            kubeconfig: None,
+            helm_repositories: OptionHelmRepositories {
+                helm_repositories: false,
+                no_helm_repositories: true,
+            },
+            resources: OptionResources {
+                resources: true,
+                no_resources: false,
+            },
+            dependencies: OptionDependencies {
+                dependencies: false,
+                no_dependencies: false,
+            },
+            dry_run: true,
+            jobs: 1,
+            vars: OptionVars::default(),
+            output: OutputFormat::Json,
+            timeout: None,
+            keep_going: false,
+            force: false,
+            state_file: None,
         };
         with_directory(None, || cmd.run()).unwrap();
     }
+
+    #[test]
+    fn test_format_summary_aligns_columns() {
+        assert_eq!(
+            "RESOURCE       TYPE   STATUS   DURATION\n\
+             group:install  shell  ok       1.5s\n\
+             app            helm   not-run  -\n",
+            format_summary(&[
+                ResourceReport {
+                    resource_path: "group:install".to_string(),
+                    resource_type: "shell".to_string(),
+                    status: ResourceStatus::Ok,
+                    duration: Some(Duration::from_millis(1500)),
+                },
+                ResourceReport {
+                    resource_path: "app".to_string(),
+                    resource_type: "helm".to_string(),
+                    status: ResourceStatus::NotRun,
+                    duration: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_command_up_fails_with_invalid_timeout() {
+        let cmd = CommandUp {
+            resources_args: vec![],
+            file: Some("tests/m8s.yaml".to_string()),
+            kubeconfig: None,
+            helm_repositories: OptionHelmRepositories {
+                helm_repositories: false,
+                no_helm_repositories: true,
+            },
+            resources: OptionResources {
+                resources: true,
+                no_resources: false,
+            },
+            dependencies: OptionDependencies {
+                dependencies: false,
+                no_dependencies: false,
+            },
+            dry_run: true,
+            jobs: 1,
+            vars: OptionVars::default(),
+            output: OutputFormat::Text,
+            timeout: Some("half an hour".to_string()),
+            keep_going: false,
+            force: false,
+            state_file: None,
+        };
+        assert_eq!(
+            "option --timeout expects a duration like 30s, 5m or 1h30m, got half an hour",
+            with_directory(None, || cmd.run())
+                .err()
+                .unwrap()
+                .to_string()
+        );
+    }
 }
@@ -0,0 +1,112 @@
+use crate::utils::{CommandRunner, NativeFileReader};
+use crate::{OptionVars, OutputFormat};
+use libm8s::file_format::ValidationError;
+use std::io;
+use std::path::Path;
+
+pub struct CommandValidate {
+    pub file: Option<String>,
+    pub vars: OptionVars,
+    pub output: OutputFormat,
+}
+
+impl CommandRunner for CommandValidate {
+    fn run(&self) -> io::Result<()> {
+        let deployment_file_path = self.file.clone().unwrap_or("m8s.yaml".to_string());
+        let validation_errors = match self.vars.get_parse_options().and_then(|parse_options| {
+            libm8s::parse_deployment_file_with_options(
+                NativeFileReader {},
+                Path::new(deployment_file_path.as_str()),
+                &parse_options,
+            )
+        }) {
+            Err(err) => vec![ValidationError {
+                resource_path: None,
+                message: err.to_string(),
+            }],
+            Ok(config) => libm8s::file_format::validate_config(&config),
+        };
+
+        match self.output {
+            OutputFormat::Text => {
+                if validation_errors.is_empty() {
+                    println!("Configuration is valid");
+                }
+                for validation_error in validation_errors.iter() {
+                    println!("{}", validation_error.message);
+                }
+            }
+            OutputFormat::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&serde_json::json!({
+                        "valid": validation_errors.is_empty(),
+                        "errors": validation_errors,
+                    }))?
+                );
+            }
+        }
+
+        if !validation_errors.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Configuration is invalid, found {} {}",
+                    validation_errors.len(),
+                    if validation_errors.len() == 1 {
+                        "problem"
+                    } else {
+                        "problems"
+                    }
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::command_validate::CommandValidate;
+    use crate::utils::{with_directory, CommandRunner};
+    use crate::{OptionVars, OutputFormat};
+
+    #[test]
+    fn test_command_validate_reads_from_m8s_yaml_by_default() {
+        with_directory(Some("tests".to_string()), || {
+            let cmd = CommandValidate {
+                file: None,
+                vars: OptionVars::default(),
+                output: OutputFormat::Text,
+            };
+            cmd.run()
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_command_validate_reports_all_problems() {
+        let cmd = CommandValidate {
+            file: Some("tests/m8s_with_multiple_problems.yaml".to_string()),
+            vars: OptionVars::default(),
+            output: OutputFormat::Json,
+        };
+        let err = with_directory(None, || cmd.run()).err().unwrap();
+        assert_eq!(
+            "Configuration is invalid, found 3 problems",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_command_validate_reports_unreadable_file() {
+        let cmd = CommandValidate {
+            file: Some("tests/does-not-exist.yaml".to_string()),
+            vars: OptionVars::default(),
+            output: OutputFormat::Text,
+        };
+        let err = with_directory(None, || cmd.run()).err().unwrap();
+        assert_eq!("Configuration is invalid, found 1 problem", err.to_string());
+    }
+}
@@ -1,13 +1,37 @@
+use crate::command_diff::{ChangesDetected, CommandDiff};
+use crate::command_down::CommandDown;
+use crate::command_graph::CommandGraph;
 use crate::command_json_schema::CommandJsonSchema;
+use crate::command_plan::CommandPlan;
 use crate::command_up::CommandUp;
+use crate::command_validate::CommandValidate;
 use crate::utils::CommandRunner;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use indexmap::IndexMap;
+use libm8s::ParseOptions;
 use std::io;
+use std::path::Path;
 
+mod command_diff;
+mod command_down;
+mod command_graph;
 mod command_json_schema;
+mod command_plan;
 mod command_up;
+mod command_validate;
 pub mod utils;
 
+pub const EXIT_CODE_ERROR: i32 = 1;
+pub const EXIT_CODE_CHANGES_DETECTED: i32 = 2;
+
+/// Maps an error returned by [`Cli::main_with_args`] to the process exit code.
+pub fn get_exit_code(err: &io::Error) -> i32 {
+    match err.get_ref() {
+        Some(inner) if inner.is::<ChangesDetected>() => EXIT_CODE_CHANGES_DETECTED,
+        _ => EXIT_CODE_ERROR,
+    }
+}
+
 #[derive(Parser)]
 #[command(about = "Declarative k8s deployment using Kubectl, Helm and more", long_about = None)]
 pub struct Cli {
@@ -22,6 +46,22 @@ struct GlobalConfigArgs {
     verbose: bool,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+enum GraphFormat {
+    /// Graphviz DOT, render with `dot -Tsvg`
+    #[default]
+    Dot,
+    /// Mermaid flowchart, renders in GitHub and GitLab markdown
+    Mermaid,
+}
+
 #[derive(Args)]
 #[group(multiple = false)]
 struct OptionHelmRepositories {
@@ -88,6 +128,45 @@ impl OptionDependencies {
     }
 }
 
+#[derive(Args, Default)]
+struct OptionVars {
+    /// Set variable KEY, overriding `vars` and variable files, can be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set_vars: Vec<String>,
+    /// Path to a YAML file with variables, overriding `vars`, can be repeated
+    #[arg(long = "var-file", value_name = "VAR_FILE")]
+    var_files: Vec<String>,
+    /// Apply the overrides of environment NAME from `environments`
+    #[arg(long = "env", value_name = "NAME")]
+    environment: Option<String>,
+}
+
+impl OptionVars {
+    /// Reads the variable files, then `--set` variables override theirs
+    fn get_parse_options(&self) -> io::Result<ParseOptions> {
+        let mut vars = IndexMap::new();
+        for var_file in self.var_files.iter() {
+            vars.extend(libm8s::parse_vars_file(
+                utils::NativeFileReader {},
+                Path::new(var_file.as_str()),
+            )?);
+        }
+        for set_var in self.set_vars.iter() {
+            let Some((key, value)) = set_var.split_once("=") else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("option --set expects KEY=VALUE, got {}", set_var),
+                ));
+            };
+            vars.insert(key.to_string(), value.to_string());
+        }
+        Ok(ParseOptions {
+            vars,
+            environment: self.environment.clone(),
+        })
+    }
+}
+
 #[derive(Subcommand)]
 enum Command {
     /// Deploys resources using the current k8s config context
@@ -112,6 +191,140 @@ enum Command {
         /// Show logs but do not actually apply changes
         #[arg(long)]
         dry_run: bool,
+        /// Number of resources to run at the same time, as long as their dependencies allow it
+        #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+        #[clap(flatten)]
+        vars: OptionVars,
+        /// Format of the progress on stdout, `json` writes one event per line and sends logs and
+        /// command output to stderr
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+        /// Fail once running the resources takes longer than DURATION, like 30m, terminating the
+        /// commands still running
+        #[arg(long, value_name = "DURATION")]
+        timeout: Option<String>,
+        /// After a failure, keep running the resources that don't depend on the failed ones, then
+        /// list all the failures
+        #[arg(long)]
+        keep_going: bool,
+        /// Run all the selected resources, even those whose inputs didn't change since they last
+        /// succeeded
+        #[arg(long)]
+        force: bool,
+        /// Path to the file recording the inputs of the resources that succeeded, defaults to
+        /// `.m8s-state.json` next to the deployment file
+        #[arg(long, value_name = "FILE")]
+        state_file: Option<String>,
+        #[clap(flatten)]
+        global_options: GlobalConfigArgs,
+    },
+    /// Shows the resources `up` would run, in order, with the exact commands, without changing
+    /// anything in the cluster
+    Plan {
+        #[arg(name = "RESOURCES")]
+        resources_args: Vec<String>,
+        /// Path to the deployment file in YAML format
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Change to DIRECTORY before doing anything
+        #[arg(short = 'C', long)]
+        directory: Option<String>,
+        /// Path to the kubeconfig file to use for CLI requests
+        #[arg(long)]
+        kubeconfig: Option<String>,
+        #[clap(flatten)]
+        dependencies: OptionDependencies,
+        #[clap(flatten)]
+        vars: OptionVars,
+        /// Format used to show the plan
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
+        #[clap(flatten)]
+        global_options: GlobalConfigArgs,
+    },
+    /// Tears down resources in reverse dependency order using the current k8s config context
+    Down {
+        #[arg(name = "RESOURCES")]
+        resources_args: Vec<String>,
+        /// Path to the deployment file in YAML format
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Change to DIRECTORY before doing anything
+        #[arg(short = 'C', long)]
+        directory: Option<String>,
+        /// Path to the kubeconfig file to use for CLI requests
+        #[arg(long)]
+        kubeconfig: Option<String>,
+        #[clap(flatten)]
+        dependencies: OptionDependencies,
+        #[clap(flatten)]
+        vars: OptionVars,
+        /// Show logs but do not actually apply changes
+        #[arg(long)]
+        dry_run: bool,
+        /// Path to the file recording the inputs of the resources that succeeded, defaults to
+        /// `.m8s-state.json` next to the deployment file
+        #[arg(long, value_name = "FILE")]
+        state_file: Option<String>,
+        #[clap(flatten)]
+        global_options: GlobalConfigArgs,
+    },
+    /// Shows what `up` would change in the cluster, exits with code 2 when there are changes
+    Diff {
+        #[arg(name = "RESOURCES")]
+        resources_args: Vec<String>,
+        /// Path to the deployment file in YAML format
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Change to DIRECTORY before doing anything
+        #[arg(short = 'C', long)]
+        directory: Option<String>,
+        /// Path to the kubeconfig file to use for CLI requests
+        #[arg(long)]
+        kubeconfig: Option<String>,
+        #[clap(flatten)]
+        helm_repositories: OptionHelmRepositories,
+        #[clap(flatten)]
+        dependencies: OptionDependencies,
+        #[clap(flatten)]
+        vars: OptionVars,
+        #[clap(flatten)]
+        global_options: GlobalConfigArgs,
+    },
+    /// Shows the dependency graph of resources
+    Graph {
+        #[arg(name = "RESOURCES")]
+        resources_args: Vec<String>,
+        /// Path to the deployment file in YAML format
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Change to DIRECTORY before doing anything
+        #[arg(short = 'C', long)]
+        directory: Option<String>,
+        #[clap(flatten)]
+        dependencies: OptionDependencies,
+        #[clap(flatten)]
+        vars: OptionVars,
+        /// Format of the graph
+        #[arg(long, value_enum, default_value_t)]
+        format: GraphFormat,
+        #[clap(flatten)]
+        global_options: GlobalConfigArgs,
+    },
+    /// Checks the deployment file and reports all the problems found
+    Validate {
+        /// Path to the deployment file in YAML format
+        #[arg(short, long)]
+        file: Option<String>,
+        /// Change to DIRECTORY before doing anything
+        #[arg(short = 'C', long)]
+        directory: Option<String>,
+        #[clap(flatten)]
+        vars: OptionVars,
+        /// Format used to report problems
+        #[arg(short, long, value_enum, default_value_t)]
+        output: OutputFormat,
         #[clap(flatten)]
         global_options: GlobalConfigArgs,
     },
@@ -137,6 +350,13 @@ impl Cli {
                 resources,
                 dependencies,
                 dry_run,
+                jobs,
+                vars,
+                output,
+                timeout,
+                keep_going,
+                force,
+                state_file,
             } => {
                 if logging {
                     utils::init_logging(global_options.verbose);
@@ -150,6 +370,132 @@ impl Cli {
                         resources,
                         dependencies,
                         dry_run,
+                        jobs,
+                        vars,
+                        output,
+                        timeout: timeout.clone(),
+                        keep_going,
+                        force,
+                        state_file: state_file.clone(),
+                    };
+                    cmd.run()
+                })
+            }
+            Command::Plan {
+                vars,
+                resources_args,
+                global_options,
+                file,
+                directory,
+                kubeconfig,
+                dependencies,
+                output,
+            } => {
+                if logging {
+                    utils::init_logging(global_options.verbose);
+                }
+                utils::with_directory(directory, || {
+                    let cmd = CommandPlan {
+                        vars,
+                        resources_args,
+                        file: file.clone(),
+                        kubeconfig: kubeconfig.clone(),
+                        dependencies,
+                        output,
+                    };
+                    cmd.run()
+                })
+            }
+            Command::Down {
+                vars,
+                resources_args,
+                global_options,
+                file,
+                directory,
+                kubeconfig,
+                dependencies,
+                dry_run,
+                state_file,
+            } => {
+                if logging {
+                    utils::init_logging(global_options.verbose);
+                }
+                utils::with_directory(directory, || {
+                    let cmd = CommandDown {
+                        vars,
+                        resources_args,
+                        file: file.clone(),
+                        kubeconfig: kubeconfig.clone(),
+                        dependencies,
+                        dry_run,
+                        state_file: state_file.clone(),
+                    };
+                    cmd.run()
+                })
+            }
+            Command::Diff {
+                vars,
+                resources_args,
+                global_options,
+                file,
+                directory,
+                kubeconfig,
+                helm_repositories,
+                dependencies,
+            } => {
+                if logging {
+                    utils::init_logging(global_options.verbose);
+                }
+                utils::with_directory(directory, || {
+                    let cmd = CommandDiff {
+                        vars,
+                        resources_args,
+                        file: file.clone(),
+                        kubeconfig: kubeconfig.clone(),
+                        helm_repositories,
+                        dependencies,
+                    };
+                    cmd.run()
+                })
+            }
+            Command::Graph {
+                vars,
+                resources_args,
+                global_options,
+                file,
+                directory,
+                dependencies,
+                format,
+            } => {
+                if logging {
+                    utils::init_logging(global_options.verbose);
+                }
+                utils::with_directory(directory, || {
+                    let cmd = CommandGraph {
+                        vars,
+                        resources_args,
+                        file: file.clone(),
+                        dependencies,
+                        format,
+                    };
+                    cmd.run()
+                })
+            }
+            Command::Validate {
+                vars,
+                global_options,
+                file,
+                directory,
+                output,
+            } => {
+                if logging {
+                    utils::init_logging(global_options.verbose);
+                }
+                utils::with_directory(directory, || {
+                    let cmd = CommandValidate {
+                        vars,
+                        file: file.clone(),
+                        output,
                     };
                     cmd.run()
                 })
@@ -1,7 +1,9 @@
+use libm8s::FileReader;
 use parking_lot::ReentrantMutex;
 use std::env::{current_dir, set_current_dir};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
-use std::{env, io};
+use std::{env, fs, io};
 
 /// Prevents race conditions in multithreaded tests
 static WITH_DIRECTORY_MUTEX: OnceLock<Arc<ReentrantMutex<()>>> = OnceLock::new();
@@ -32,6 +34,25 @@ pub fn init_logging(verbose: bool) {
     env_logger::init();
 }
 
+/// `--state-file`, or `.m8s-state.json` next to the deployment file
+pub(crate) fn get_state_file_path(
+    state_file: Option<&String>,
+    deployment_file_path: &str,
+) -> PathBuf {
+    match state_file {
+        Some(state_file) => PathBuf::from(state_file),
+        None => Path::new(deployment_file_path).with_file_name(".m8s-state.json"),
+    }
+}
+
 pub trait CommandRunner {
     fn run(&self) -> io::Result<()>;
 }
+
+pub(crate) struct NativeFileReader;
+
+impl FileReader for NativeFileReader {
+    fn read_to_string(&self, file_path: &Path) -> io::Result<String> {
+        fs::read_to_string(file_path)
+    }
+}
@@ -1,4 +1,5 @@
-use libm8scmd::Cli;
+use libm8scmd::{get_exit_code, Cli, EXIT_CODE_ERROR};
+use std::io;
 
 #[test]
 fn test_main_with_args_for_command_up_changes_and_restores_directory() {
@@ -8,7 +9,210 @@ fn test_main_with_args_for_command_up_changes_and_restores_directory() {
     )
     .unwrap();
 }
+
+#[test]
+fn test_main_with_args_for_command_up_with_vars_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "up",
+            "--dry-run",
+            "--directory",
+            "tests",
+            "--file",
+            "m8s_with_vars.yaml",
+            "--var-file",
+            "vars.yaml",
+            "--set",
+            "message=from-cli",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_for_command_up_with_env_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "up",
+            "--dry-run",
+            "--file",
+            "tests/m8s_with_environments.yaml",
+            "--env",
+            "production",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_applies_env_for_all_commands() {
+    for command in ["plan", "diff", "down", "graph"] {
+        let err = Cli::main_with_args(
+            vec![
+                "m8s",
+                command,
+                "--file",
+                "tests/m8s_with_environments.yaml",
+                "--env",
+                "staging",
+            ],
+            false,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "Configuration is invalid, environment doesn't exist: staging",
+            err.to_string(),
+            "m8s {} --env staging",
+            command
+        );
+    }
+
+    // Validate reports the problem along with the others it finds
+    let err = Cli::main_with_args(
+        vec![
+            "m8s",
+            "validate",
+            "--file",
+            "tests/m8s_with_environments.yaml",
+            "--env",
+            "staging",
+        ],
+        false,
+    )
+    .err()
+    .unwrap();
+    assert_eq!("Configuration is invalid, found 1 problem", err.to_string());
+}
+
+#[test]
+fn test_main_with_args_for_command_plan_with_vars_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "plan",
+            "--directory",
+            "tests",
+            "--file",
+            "m8s_with_vars.yaml",
+            "--var-file",
+            "vars.yaml",
+            "--set",
+            "message=from-cli",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_for_command_up_with_invalid_set_fails() {
+    let err = Cli::main_with_args(
+        vec![
+            "m8s",
+            "up",
+            "--dry-run",
+            "--file",
+            "tests/m8s_with_vars.yaml",
+            "--set",
+            "message",
+        ],
+        false,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+        "option --set expects KEY=VALUE, got message",
+        err.to_string()
+    );
+}
+
 #[test]
 fn test_main_with_args_for_json_schema_runs() {
     Cli::main_with_args(vec!["m8s", "json-schema"], false).unwrap();
 }
+
+#[test]
+fn test_main_with_args_for_command_down_changes_and_restores_directory() {
+    Cli::main_with_args(
+        vec!["m8s", "down", "--dry-run", "--directory", "tests"],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_for_diff_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "diff",
+            "--no-helm-repositories",
+            "--directory",
+            "tests",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_get_exit_code_distinguishes_changes_from_errors() {
+    assert_eq!(
+        EXIT_CODE_ERROR,
+        get_exit_code(&io::Error::other("Something failed"))
+    );
+    assert_eq!(
+        EXIT_CODE_ERROR,
+        get_exit_code(
+            &Cli::main_with_args(vec!["m8s", "up", "--file", "does-not-exist.yaml"], false)
+                .err()
+                .unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_main_with_args_for_validate_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "validate",
+            "--output",
+            "json",
+            "--directory",
+            "tests",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_for_graph_runs() {
+    Cli::main_with_args(
+        vec![
+            "m8s",
+            "graph",
+            "--format",
+            "mermaid",
+            "--directory",
+            "tests",
+        ],
+        false,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_main_with_args_for_plan_runs() {
+    Cli::main_with_args(
+        vec!["m8s", "plan", "--output", "json", "--directory", "tests"],
+        false,
+    )
+    .unwrap();
+}
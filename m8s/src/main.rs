@@ -1,4 +1,4 @@
-use log::error;
+use log::{error, info};
 use std::{env, process};
 
 fn main() {
@@ -6,7 +6,12 @@ fn main() {
     let args_refs = args.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
 
     if let Err(err) = libm8scmd::Cli::main_with_args(args_refs, true) {
-        error!("{}", err);
-        process::exit(1);
+        let exit_code = libm8scmd::get_exit_code(&err);
+        if exit_code == libm8scmd::EXIT_CODE_CHANGES_DETECTED {
+            info!("{}", err);
+        } else {
+            error!("{}", err);
+        }
+        process::exit(exit_code);
     }
 }